@@ -1,20 +1,33 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
-use transaction::{OpenTransaction, RaiHash, Transaction};
+use transaction::{OpenTransaction, RaiHash, RaiWork, StateKind, Transaction};
 use types::{Balance, Hash, PubKey};
 use errors::Failure;
-use genesis;
+use network::NetworkParams;
+use work::Difficulty;
+
+/// The most transactions kept competing for a single root
+pub const MAX_CONTENDERS: usize = 16;
+/// The most roots that contenders are kept for at once
+pub const MAX_FORKS: usize = 4096;
 
 pub trait BlockStorage {
     /// Lookup a transaction based on its hash
     fn lookup(&mut self, hash: Hash) -> Option<&Transaction>;
     /// Find the most recent transaction belonging to an account
     fn find_head(&mut self, pubkey: PubKey) -> Option<Hash>;
+    /// Find the transaction that follows a given one in its account chain
+    fn find_successor(&mut self, hash: Hash) -> Option<Hash>;
     /// Find the public key that used to sign a given block
     fn find_key(&mut self, hash: Hash) -> Option<PubKey> {
+        // State blocks carry their account, so there is no need to walk the chain
+        if let &Transaction::State(ref s) = self.lookup(hash)? {
+            return Some(s.account);
+        }
         self.find_open(hash).map(|o| o.account)
     }
-    /// Find the first transaction in an account's ledger
+    /// Find the first transaction in an account's ledger, or `None` if the account was opened
+    /// with a state block
     fn find_open(&mut self, mut hash: Hash) -> Option<&OpenTransaction> {
         // The first lookup can fail, which is why we do this
         hash = match self.lookup(hash)? {
@@ -22,6 +35,8 @@ pub trait BlockStorage {
             &Transaction::Send(ref t) => t.previous,
             &Transaction::Receive(ref t) => t.previous,
             &Transaction::Change(ref t) => t.previous,
+            &Transaction::State(ref t) if t.is_open() => return None,
+            &Transaction::State(ref t) => t.previous,
         };
         loop {
             match self.lookup(hash) {
@@ -29,6 +44,8 @@ pub trait BlockStorage {
                 Some(&Transaction::Send(ref t)) => hash = t.previous.clone(),
                 Some(&Transaction::Receive(ref t)) => hash = t.previous.clone(),
                 Some(&Transaction::Change(ref t)) => hash = t.previous.clone(),
+                Some(&Transaction::State(ref t)) if t.is_open() => return None,
+                Some(&Transaction::State(ref t)) => hash = t.previous.clone(),
                 None => unreachable!(), // This should only ever happen if the ledger is in an invalid state
             }
         }
@@ -40,124 +57,656 @@ pub trait BlockStorage {
     }
     /// Find the balance in the account at the time of the given transaction
     fn find_balance(&mut self, hash: Hash) -> Option<Balance>;
+    /// Given the hash of a send block, find the destination account and the amount sent
+    fn find_send(&mut self, hash: Hash) -> Result<(PubKey, Balance), Failure> {
+        let (destination, previous, balance) = match self.lookup(hash).ok_or(Failure::Missing)? {
+            &Transaction::Send(ref s) => (s.destination, s.previous, s.balance),
+            &Transaction::State(ref s) if !s.is_open() => {
+                (PubKey(s.link.0), s.previous, s.balance)
+            }
+            _ => return Err(Failure::Invalid),
+        };
+        let prev_bal = self.find_balance(previous).ok_or(Failure::Unreachable)?;
+        if balance < prev_bal {
+            Ok((destination, prev_bal - balance))
+        } else {
+            // A state block that didn't decrease the balance isn't a send
+            Err(Failure::Invalid)
+        }
+    }
     /// Given the hash of a send block, check if it has been spent yet
     fn is_unspent(&mut self, hash: Hash) -> bool;
+    /// List the sends that are waiting to be received by an account, in no particular order
+    fn find_pending(&mut self, account: PubKey) -> Vec<(Hash, Pending)>;
+    /// Find the representative an account currently delegates its balance to
+    fn find_representative(&mut self, account: PubKey) -> Option<PubKey>;
+    /// Summarize an account's current state, or `None` if it hasn't been opened
+    fn account_info(&mut self, account: PubKey) -> Option<AccountInfo>;
+    /// List up to `count` accounts with their heads, in account order starting from `start`
+    fn find_frontiers(&mut self, start: PubKey, count: usize) -> Vec<(PubKey, Hash)>;
+    /// Find the total balance delegated to a representative
+    fn find_weight(&mut self, representative: PubKey) -> Balance;
+    /// The work thresholds transactions must meet to be inserted
+    fn difficulty(&self) -> Difficulty;
+    /// The parameters of the network this ledger belongs to
+    fn network(&self) -> &NetworkParams;
+    /// Find the position of a transaction in its account chain, starting at 1 for the first
+    fn find_height(&mut self, hash: Hash) -> Option<u64>;
+    /// The height of the most recently confirmed transaction in an account, or 0 if none are
+    fn find_confirmation_height(&mut self, account: PubKey) -> u64;
+    /// Check whether a transaction has been cemented
+    fn is_confirmed(&mut self, hash: Hash) -> bool {
+        match (self.find_key(hash), self.find_height(hash)) {
+            (Some(key), Some(height)) => height <= self.find_confirmation_height(key),
+            _ => false,
+        }
+    }
+
+    /// Find the send received by a transaction, or `None` if it isn't a receive
+    fn find_received(&mut self, hash: Hash) -> Option<Hash> {
+        let (link, previous, balance) = match self.lookup(hash)? {
+            &Transaction::Open(ref o) => return Some(o.source),
+            &Transaction::Receive(ref r) => return Some(r.source),
+            &Transaction::State(ref s) => (s.link, s.previous, s.balance),
+            _ => return None,
+        };
+        let prev_bal = if previous == Hash::default() {
+            Balance(0)
+        } else {
+            self.find_balance(previous)?
+        };
+        if balance > prev_bal {
+            Some(link)
+        } else {
+            None
+        }
+    }
+    /// List the roots that have more than one transaction competing for them, see
+    /// `Transaction::root`
+    fn find_forks(&mut self) -> Vec<Hash>;
+    /// List the transactions competing for a root, starting with the one currently in the ledger
+    fn find_conflicts(&mut self, root: Hash) -> Vec<Transaction>;
+    /// End the election for a root by choosing the transaction that belongs in the ledger
+    ///
+    /// If the winner isn't the transaction already in the ledger, that transaction is rolled back
+    /// along with everything that depends on it, and replaced by the winner. The losers are
+    /// discarded. If the winner can't be inserted the ledger is put back the way it was, and the
+    /// winner stays a contender.
+    fn resolve_fork(&mut self, root: Hash, winner: Hash) -> Result<(), Failure>;
+    /// Remove a transaction and every transaction after it on its account chain
+    ///
+    /// Sends that have already been received are only removed after the receiving chain has been
+    /// rolled back, and the sources of removed receives become unspent again. Returns the removed
+    /// transactions newest first, so inserting them in reverse restores the ledger. If the rollback
+    /// fails part way, whatever was removed is inserted again before the error is returned.
+    fn rollback(&mut self, hash: Hash) -> Result<Vec<Transaction>, Failure>;
+    /// Confirm a transaction, along with every transaction before it on its account chain and
+    /// every send those transactions receive
+    ///
+    /// Confirmed transactions are permanent, they can't be rolled back or lose a fork.
+    fn cement(&mut self, hash: Hash) -> Result<(), Failure>;
 
     /// Try to insert a new transaction
     fn insert(&mut self, tx: Transaction) -> Result<(), Failure>;
 }
 
+pub(crate) mod sealed {
+    use transaction::Transaction;
+    use errors::Failure;
+
+    /// Lets code generic over `BlockStorage` reach the crate's own `insert_verified` helpers
+    ///
+    /// The trait can't be named outside the crate, so nothing else can skip signature checks.
+    pub trait InsertVerified {
+        fn insert_verified(&mut self, tx: Transaction) -> Result<(), Failure>;
+    }
+}
+
+/// The current state of an account
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountInfo {
+    /// The first transaction in the account
+    pub open: Hash,
+    /// The most recent transaction in the account
+    pub head: Hash,
+    pub representative: PubKey,
+    pub balance: Balance,
+    /// The number of transactions in the account
+    pub block_count: u64,
+    /// A counter that increases whenever any account in the ledger changes, the value it had
+    /// when this account last changed
+    pub modified: u64,
+}
+
+/// A send that hasn't been received by its destination yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pending {
+    /// The account that made the send
+    pub source: PubKey,
+    pub amount: Balance,
+}
+
+#[derive(Debug)]
+struct Entry {
+    tx: Transaction,
+    /// The balance of the account after this transaction
+    balance: Balance,
+    /// The representative of the account after this transaction
+    representative: PubKey,
+    /// The number of transactions in the account up to and including this one
+    height: u64,
+}
+
+/// Where `Storage::insert_impl` put a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Inserted {
+    Ledger,
+    /// Kept as a new contender for a position that is already occupied
+    Contender,
+}
+
+impl Inserted {
+    /// What `insert` reports, which doesn't tell new contenders apart from known ones
+    pub(crate) fn into_result(self) -> Result<(), Failure> {
+        match self {
+            Inserted::Ledger => Ok(()),
+            Inserted::Contender => Err(Failure::Fork),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Storage {
-    transactions: HashMap<Hash, (Transaction, Balance)>,
-    heads: HashMap<PubKey, Hash>,
-    unspent: HashSet<Hash>,
+    transactions: HashMap<Hash, Entry>,
+    accounts: HashMap<PubKey, AccountInfo>,
+    /// The transaction following each transaction in its account chain
+    successors: HashMap<Hash, Hash>,
+    /// Counts every change to an account, used for `AccountInfo::modified`
+    modified: u64,
+    /// Unspent sends, mapped to their destination
+    unspent: HashMap<Hash, PubKey>,
+    /// Unspent sends grouped by destination
+    pending: HashMap<PubKey, HashMap<Hash, Pending>>,
+    /// The total balance delegated to each representative
+    weights: HashMap<PubKey, Balance>,
+    /// Transactions competing with the ones in the ledger, grouped by root
+    forks: HashMap<Hash, HashMap<Hash, Transaction>>,
+    /// The height up to which each account's transactions have been cemented
+    confirmed: HashMap<PubKey, u64>,
+    network: NetworkParams,
 }
 
 impl Storage {
-    /// Create a new BlockStorage
+    /// Create a new BlockStorage for the live network
     pub fn new() -> Self {
-        let mut transactions = HashMap::new();
-        let mut heads = HashMap::new();
-        let unspent = HashSet::new();
-        transactions.insert(
-            genesis::LIVE_BLOCK.hash(),
-            (Transaction::Open(genesis::LIVE_BLOCK), genesis::BALANCE),
-        );
-        heads.insert(genesis::LIVE_BLOCK.account, genesis::LIVE_BLOCK.hash());
-        Self {
-            transactions,
-            heads,
-            unspent,
-        }
+        Self::with_network(NetworkParams::live())
     }
     pub(crate) fn new_test() -> Self {
+        Self::with_network(NetworkParams::test())
+    }
+    /// Create a new BlockStorage containing only the genesis block of the given network
+    pub fn with_network(network: NetworkParams) -> Self {
         let mut transactions = HashMap::new();
-        let mut heads = HashMap::new();
-        let unspent = HashSet::new();
+        let mut accounts = HashMap::new();
+        let mut weights = HashMap::new();
+        let mut confirmed = HashMap::new();
+        let hash = network.genesis.hash();
+        let info = AccountInfo {
+            open: hash,
+            head: hash,
+            representative: network.genesis.representative,
+            balance: network.genesis_balance,
+            block_count: 1,
+            modified: 0,
+        };
+        accounts.insert(network.genesis.account, info);
+        // The genesis block has no real source, so it is confirmed from the start
+        confirmed.insert(network.genesis.account, 1);
+        weights.insert(network.genesis.representative, network.genesis_balance);
         transactions.insert(
-            genesis::TEST_BLOCK.hash(),
-            (Transaction::Open(genesis::TEST_BLOCK), genesis::BALANCE),
+            hash,
+            Entry {
+                tx: Transaction::Open(network.genesis.clone()),
+                balance: network.genesis_balance,
+                representative: network.genesis.representative,
+                height: 1,
+            },
         );
-        heads.insert(genesis::TEST_BLOCK.account, genesis::TEST_BLOCK.hash());
         Self {
             transactions,
-            heads,
-            unspent,
+            accounts,
+            successors: HashMap::new(),
+            modified: 0,
+            unspent: HashMap::new(),
+            pending: HashMap::new(),
+            weights,
+            forks: HashMap::new(),
+            confirmed,
+            network,
+        }
+    }
+    fn add_pending(&mut self, hash: Hash, destination: PubKey, pending: Pending) {
+        self.unspent.insert(hash, destination);
+        self.pending
+            .entry(destination)
+            .or_insert_with(HashMap::new)
+            .insert(hash, pending);
+    }
+    /// Find the transaction in the ledger that occupies a root, see `Transaction::root`
+    fn find_incumbent(&mut self, root: Hash) -> Option<Hash> {
+        if self.transactions.contains_key(&root) {
+            self.find_successor(root)
+        } else {
+            // Open blocks use their account as their root
+            self.accounts.get(&PubKey(root.0)).map(|a| a.open)
+        }
+    }
+    /// Remove the head of an account, first rolling back any receive of it if it is a send
+    fn rollback_head(
+        &mut self,
+        key: PubKey,
+        head: Hash,
+        removed: &mut Vec<Transaction>,
+    ) -> Result<(), Failure> {
+        if let Ok((destination, _)) = self.find_send(head) {
+            // Rolling back the receive puts the send back in the unspent set
+            while !self.is_unspent(head) {
+                let dest_head = self.find_head(destination).ok_or(Failure::Unreachable)?;
+                self.rollback_head(destination, dest_head, removed)?;
+            }
+            self.remove_pending(head);
+        }
+        if let Some(source) = self.find_received(head) {
+            let (destination, amount) = self.find_send(source)?;
+            let sender = self.find_key(source).ok_or(Failure::Unreachable)?;
+            let pending = Pending {
+                source: sender,
+                amount,
+            };
+            self.add_pending(source, destination, pending);
+        }
+        let entry = self.transactions.remove(&head).ok_or(Failure::Unreachable)?;
+        self.remove_weight(entry.representative, entry.balance);
+        self.modified += 1;
+        match entry.tx.previous() {
+            Some(previous) => {
+                let (rep, bal, height) = {
+                    let e = self.transactions.get(&previous).ok_or(Failure::Unreachable)?;
+                    (e.representative, e.balance, e.height)
+                };
+                self.add_weight(rep, bal);
+                self.successors.remove(&previous);
+                let info = self.accounts.get_mut(&key).ok_or(Failure::Unreachable)?;
+                info.head = previous;
+                info.representative = rep;
+                info.balance = bal;
+                info.block_count = height;
+                info.modified = self.modified;
+            }
+            None => {
+                self.accounts.remove(&key);
+            }
+        }
+        removed.push(entry.tx);
+        Ok(())
+    }
+    /// Keep a transaction as a contender for its root, unless too many are kept already
+    ///
+    /// Returns whether the transaction wasn't kept before and is now.
+    fn add_contender(&mut self, tx: Transaction) -> bool {
+        let root = tx.root();
+        let full = match self.forks.get(&root) {
+            Some(contenders) => {
+                if contenders.contains_key(&tx.hash()) {
+                    return false;
+                }
+                contenders.len() >= MAX_CONTENDERS
+            }
+            None => self.forks.len() >= MAX_FORKS,
+        };
+        if !full {
+            self.forks
+                .entry(root)
+                .or_insert_with(HashMap::new)
+                .insert(tx.hash(), tx);
+        }
+        !full
+    }
+    /// Insert a transaction whose signature has already been verified, e.x. by `verify_parallel`
+    ///
+    /// Everything but the signature is still checked, so this must never be given a transaction
+    /// that hasn't been verified.
+    pub(crate) fn insert_verified(&mut self, tx: Transaction) -> Result<(), Failure> {
+        self.insert_impl(tx, false).and_then(Inserted::into_result)
+    }
+    /// Insert rolled back transactions again, oldest first
+    fn restore(&mut self, removed: Vec<Transaction>) -> Result<(), Failure> {
+        for tx in removed.into_iter().rev() {
+            // These were all in the ledger a moment ago, so failing here means there is a bug
+            match self.insert_impl(tx, false) {
+                Ok(Inserted::Ledger) => {}
+                _ => return Err(Failure::Unreachable),
+            }
+        }
+        Ok(())
+    }
+    /// Insert a transaction, or keep it as a contender if its position is already occupied
+    ///
+    /// Forks that were kept already, or can't be kept because too many are, fail with `Fork`.
+    pub(crate) fn insert_impl(
+        &mut self,
+        tx: Transaction,
+        verify_sig: bool,
+    ) -> Result<Inserted, Failure> {
+        use transaction::Transaction::*;
+        let hash = tx.hash();
+        if self.transactions.contains_key(&hash) {
+            return Err(Failure::Duplicate);
+        }
+        let parent = tx.previous();
+        let key = match (&tx, parent) {
+            (&Open(ref o), _) => o.account,
+            (&State(ref s), None) => s.account,
+            (_, Some(parent)) => self.find_key(parent).ok_or(Failure::Missing)?,
+            (_, None) => return Err(Failure::Unreachable),
+        };
+        if let State(ref s) = tx {
+            if s.account != key {
+                return Err(Failure::Invalid);
+            }
+        }
+        if self.find_head(key) != parent {
+            // Another block already occupies this position, keep this one as a contender as long
+            // as it is properly signed
+            if verify_sig {
+                tx.verify_sig(self)?;
+            }
+            tx.verify_work(self.network.difficulty.lowest())?;
+            if self.add_contender(tx) {
+                return Ok(Inserted::Contender);
+            }
+            return Err(Failure::Fork);
+        }
+        if verify_sig {
+            tx.verify(self)?;
+        } else {
+            tx.verify_without_sig(self)?;
+        }
+        // Legacy blocks can't be appended once an account has been upgraded to state blocks
+        if let Some(parent) = parent {
+            let upgraded = match self.lookup(parent) {
+                Some(&State(_)) => true,
+                _ => false,
+            };
+            let legacy = match tx {
+                State(_) => false,
+                _ => true,
+            };
+            if upgraded && legacy {
+                return Err(Failure::Invalid);
+            }
+        }
+        let bal = match tx {
+            // The balance of a new account is the amount sent to it
+            Open(ref o) => self.find_send(o.source)?.1,
+            Receive(ref r) => {
+                let (_, gain) = self.find_send(r.source)?;
+                self.find_balance(r.previous).ok_or(Failure::Unreachable)? + gain
+            }
+            Send(ref s) => s.balance,
+            Change(ref c) => self.find_balance(c.previous).ok_or(Failure::Unreachable)?,
+            State(ref s) => s.balance,
+        };
+        // Move the account's weight from its old representative to its new one
+        let old = match parent {
+            Some(p) => {
+                let e = self.transactions.get(&p).ok_or(Failure::Unreachable)?;
+                Some((e.representative, e.balance, e.height))
+            }
+            None => None,
+        };
+        let representative = match tx {
+            Open(ref o) => o.representative,
+            Change(ref c) => c.representative,
+            State(ref s) => s.representative,
+            Send(_) | Receive(_) => old.ok_or(Failure::Unreachable)?.0,
+        };
+        // The send this transaction receives, if any
+        let received = match tx {
+            Open(ref o) => Some(o.source),
+            Receive(ref r) => Some(r.source),
+            State(ref s) => match s.kind(self)? {
+                StateKind::Open | StateKind::Receive => Some(s.link),
+                StateKind::Send | StateKind::Change => None,
+            },
+            Send(_) | Change(_) => None,
+        };
+        // Nothing can fail from here on, so the ledger is never left half updated
+        let height = match old {
+            Some((old_rep, old_bal, old_height)) => {
+                self.remove_weight(old_rep, old_bal);
+                old_height + 1
+            }
+            None => 1,
+        };
+        self.add_weight(representative, bal);
+        let entry = Entry {
+            tx,
+            balance: bal,
+            representative,
+            height,
+        };
+        self.transactions.insert(hash, entry);
+        if let Some(parent) = parent {
+            self.successors.insert(parent, hash);
+        }
+        self.modified += 1;
+        let open = match self.accounts.get(&key) {
+            Some(info) => info.open,
+            None => hash,
+        };
+        let info = AccountInfo {
+            open,
+            head: hash,
+            representative,
+            balance: bal,
+            block_count: height,
+            modified: self.modified,
+        };
+        self.accounts.insert(key, info);
+        if let Some(source) = received {
+            self.remove_pending(source);
+        }
+        if let Ok((destination, amount)) = self.find_send(hash) {
+            let pending = Pending {
+                source: key,
+                amount,
+            };
+            self.add_pending(hash, destination, pending);
+        }
+        Ok(Inserted::Ledger)
+    }
+    fn add_weight(&mut self, representative: PubKey, amount: Balance) {
+        let weight = self.find_weight(representative) + amount;
+        self.weights.insert(representative, weight);
+    }
+    fn remove_weight(&mut self, representative: PubKey, amount: Balance) {
+        let weight = self.find_weight(representative) - amount;
+        if weight == Balance(0) {
+            self.weights.remove(&representative);
+        } else {
+            self.weights.insert(representative, weight);
+        }
+    }
+    fn remove_pending(&mut self, hash: Hash) {
+        if let Some(destination) = self.unspent.remove(&hash) {
+            let empty = match self.pending.get_mut(&destination) {
+                Some(p) => {
+                    p.remove(&hash);
+                    p.is_empty()
+                }
+                None => false,
+            };
+            if empty {
+                self.pending.remove(&destination);
+            }
         }
     }
 }
 
 impl BlockStorage for Storage {
     fn lookup(&mut self, hash: Hash) -> Option<&Transaction> {
-        self.transactions.get(&hash).map(|&(ref t, _)| t)
+        self.transactions.get(&hash).map(|e| &e.tx)
+    }
+    fn find_successor(&mut self, hash: Hash) -> Option<Hash> {
+        self.successors.get(&hash).cloned()
     }
     fn find_head(&mut self, pubkey: PubKey) -> Option<Hash> {
-        self.heads.get(&pubkey).map(|&x| x)
+        self.accounts.get(&pubkey).map(|a| a.head)
     }
     fn find_balance(&mut self, hash: Hash) -> Option<Balance> {
-        self.transactions.get(&hash).map(|&(_, b)| b)
+        self.transactions.get(&hash).map(|e| e.balance)
     }
     fn is_unspent(&mut self, hash: Hash) -> bool {
-        self.unspent.contains(&hash)
+        self.unspent.contains_key(&hash)
     }
-    fn insert(&mut self, tx: Transaction) -> Result<(), Failure> {
-        tx.verify(self)?;
-        use transaction::Transaction::*;
-        let (bal, key, parent) = match tx {
-            Open(ref o) => {
-                // Find the balance of this account by finding the amount
-                let bal = self.find_balance(o.source).ok_or(Failure::Unreachable)?;
-                let prev = match self.lookup(o.source).ok_or(Failure::Unreachable)? {
-                    &Send(ref s) => s.previous,
-                    _ => return Err(Failure::Invalid),
-                };
-                let prev_bal = self.find_balance(prev).ok_or(Failure::Unreachable)?;
-                let bal = prev_bal - bal;
-                (bal, o.account, None)
+    fn find_pending(&mut self, account: PubKey) -> Vec<(Hash, Pending)> {
+        self.pending
+            .get(&account)
+            .map(|p| p.iter().map(|(&h, &p)| (h, p)).collect())
+            .unwrap_or_default()
+    }
+    fn find_representative(&mut self, account: PubKey) -> Option<PubKey> {
+        self.accounts.get(&account).map(|a| a.representative)
+    }
+    fn account_info(&mut self, account: PubKey) -> Option<AccountInfo> {
+        self.accounts.get(&account).cloned()
+    }
+    fn find_frontiers(&mut self, start: PubKey, count: usize) -> Vec<(PubKey, Hash)> {
+        let mut frontiers: Vec<(PubKey, Hash)> = self.accounts
+            .iter()
+            .filter(|&(&account, _)| account >= start)
+            .map(|(&account, info)| (account, info.head))
+            .collect();
+        frontiers.sort();
+        frontiers.truncate(count);
+        frontiers
+    }
+    fn find_weight(&mut self, representative: PubKey) -> Balance {
+        self.weights
+            .get(&representative)
+            .map(|&w| w)
+            .unwrap_or(Balance(0))
+    }
+    fn difficulty(&self) -> Difficulty {
+        self.network.difficulty
+    }
+    fn network(&self) -> &NetworkParams {
+        &self.network
+    }
+    fn find_height(&mut self, hash: Hash) -> Option<u64> {
+        self.transactions.get(&hash).map(|e| e.height)
+    }
+    fn find_confirmation_height(&mut self, account: PubKey) -> u64 {
+        self.confirmed.get(&account).map(|&h| h).unwrap_or(0)
+    }
+    fn find_forks(&mut self) -> Vec<Hash> {
+        self.forks.keys().cloned().collect()
+    }
+    fn find_conflicts(&mut self, root: Hash) -> Vec<Transaction> {
+        let mut conflicts = Vec::new();
+        if let Some(incumbent) = self.find_incumbent(root) {
+            conflicts.push(self.transactions[&incumbent].tx.clone());
+        }
+        if let Some(forks) = self.forks.get(&root) {
+            conflicts.extend(forks.values().cloned());
+        }
+        conflicts
+    }
+    fn resolve_fork(&mut self, root: Hash, winner: Hash) -> Result<(), Failure> {
+        let incumbent = self.find_incumbent(root);
+        if incumbent != Some(winner) {
+            if let Some(incumbent) = incumbent {
+                if self.is_confirmed(incumbent) {
+                    return Err(Failure::Confirmed);
+                }
+            }
+            let tx = self.forks
+                .get_mut(&root)
+                .and_then(|f| f.remove(&winner))
+                .ok_or(Failure::Missing)?;
+            let removed = match incumbent.map(|i| self.rollback(i)) {
+                Some(Ok(removed)) => removed,
+                Some(Err(e)) => {
+                    self.add_contender(tx);
+                    return Err(e);
+                }
+                None => Vec::new(),
+            };
+            if let Err(e) = self.insert(tx.clone()) {
+                // Put the ledger back the way it was
+                self.restore(removed)?;
+                self.add_contender(tx);
+                return Err(e);
             }
-            Receive(ref r) => {
-                // Find the balance of this account by finding the amount
-                let bal = self.find_balance(r.source).ok_or(Failure::Unreachable)?;
-                let prev = match self.lookup(r.source).ok_or(Failure::Unreachable)? {
-                    &Send(ref s) => s.previous,
-                    _ => return Err(Failure::Invalid),
-                };
-                let prev_bal = self.find_balance(prev).ok_or(Failure::Unreachable)?;
-                let gain = prev_bal - bal;
-                let bal = self.find_balance(r.previous).ok_or(Failure::Unreachable)? + gain;
-                let key = self.find_key(r.previous).ok_or(Failure::Unreachable)?;
-                (bal, key, Some(r.previous))
-            }
-            Send(ref s) => (
-                s.balance,
-                self.find_key(s.previous).ok_or(Failure::Unreachable)?,
-                Some(s.previous),
-            ),
-            Change(ref c) => (
-                self.find_balance(c.previous).ok_or(Failure::Unreachable)?,
-                self.find_key(c.previous).ok_or(Failure::Unreachable)?,
-                Some(c.previous),
-            ),
-        };
-        if self.find_head(key) != parent {
-            return Err(Failure::Fork);
         }
-        let hash = tx.hash();
-        match tx {
-            Send(_) => {
-                self.unspent.insert(hash);
+        self.forks.remove(&root);
+        Ok(())
+    }
+    fn rollback(&mut self, hash: Hash) -> Result<Vec<Transaction>, Failure> {
+        let key = self.find_key(hash).ok_or(Failure::Missing)?;
+        // Everything depending on an unconfirmed transaction is unconfirmed too, so this is the
+        // only check needed
+        if self.is_confirmed(hash) {
+            return Err(Failure::Confirmed);
+        }
+        let mut removed = Vec::new();
+        loop {
+            let result = self.find_head(key)
+                .ok_or(Failure::Unreachable)
+                .and_then(|head| self.rollback_head(key, head, &mut removed).map(|_| head));
+            match result {
+                Ok(head) if head == hash => return Ok(removed),
+                Ok(_) => {}
+                Err(e) => {
+                    // Don't leave the ledger half rolled back
+                    self.restore(removed)?;
+                    return Err(e);
+                }
             }
-            Open(ref o) => {
-                self.unspent.remove(&o.source);
+        }
+    }
+    fn cement(&mut self, hash: Hash) -> Result<(), Failure> {
+        let mut stack = vec![hash];
+        while let Some(hash) = stack.pop() {
+            let key = self.find_key(hash).ok_or(Failure::Missing)?;
+            let height = self.find_height(hash).ok_or(Failure::Missing)?;
+            let confirmed = self.find_confirmation_height(key);
+            if height <= confirmed {
+                continue;
             }
-            Receive(ref r) => {
-                self.unspent.remove(&r.source);
+            // Walk back to the confirmed part of the chain, collecting the sends it received
+            let mut current = hash;
+            for _ in confirmed..height {
+                if let Some(source) = self.find_received(current) {
+                    stack.push(source);
+                }
+                match self.transactions[&current].tx.previous() {
+                    Some(previous) => current = previous,
+                    None => break,
+                }
             }
-            _ => {}
-        };
-        self.transactions.insert(hash, (tx, bal));
-        self.heads.insert(key, hash);
+            self.confirmed.insert(key, height);
+        }
         Ok(())
     }
+    fn insert(&mut self, tx: Transaction) -> Result<(), Failure> {
+        self.insert_impl(tx, true).and_then(Inserted::into_result)
+    }
+}
+
+impl sealed::InsertVerified for Storage {
+    fn insert_verified(&mut self, tx: Transaction) -> Result<(), Failure> {
+        Storage::insert_verified(self, tx)
+    }
 }
@@ -0,0 +1,248 @@
+use std::fmt::Debug;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use blockstorage::BlockStorage;
+use blockstorage::sealed::InsertVerified;
+use connections::Connections;
+use import::{ImportSummary, Importer};
+use protocol::{BulkPull, FrontierReq, Header, Message};
+use serialize::BlockType;
+use transaction::Transaction;
+use types::{Hash, PubKey};
+
+/// How long the client waits for the server before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The most connections a `BootstrapServer` serves at once
+const MAX_CONNECTIONS: usize = 16;
+
+fn invalid_data<E: Debug>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+fn read_message<R: Read>(stream: &mut R, magic: [u8; 2]) -> io::Result<Message> {
+    let mut header = [0; Header::SIZE];
+    stream.read_exact(&mut header)?;
+    let header = Header::deserialize(magic, &header).map_err(invalid_data)?;
+    let mut body = vec![0; header.body_size().map_err(invalid_data)?];
+    stream.read_exact(&mut body)?;
+    Message::deserialize(&header, &body).map_err(invalid_data)
+}
+
+/// Write the heads of accounts as pairs of account and head, ending with a pair of zeroes
+fn write_frontiers<S: BlockStorage>(storage: &mut S, req: FrontierReq, out: &mut Vec<u8>) {
+    for (account, head) in storage.find_frontiers(req.start, req.count as usize) {
+        out.extend_from_slice(account.as_ref());
+        out.extend_from_slice(&head.0);
+    }
+    out.extend_from_slice(&[0; 64]);
+}
+
+/// Write an account chain from the newest block back, each block prefixed with its type and the
+/// whole response ending with the `NotABlock` type
+fn write_bulk_pull<S: BlockStorage>(storage: &mut S, pull: BulkPull, out: &mut Vec<u8>) {
+    let mut next = match storage.account_info(PubKey(pull.start.0)) {
+        Some(info) => Some(info.head),
+        None => Some(pull.start),
+    };
+    while let Some(hash) = next {
+        if hash == pull.end {
+            break;
+        }
+        next = match storage.lookup(hash) {
+            Some(tx) => {
+                out.push(tx.block_type() as u8);
+                tx.serialize(out);
+                tx.previous()
+            }
+            None => None,
+        };
+    }
+    out.push(BlockType::NotABlock as u8);
+}
+
+fn serve<S: BlockStorage>(
+    mut stream: TcpStream,
+    storage: &Mutex<S>,
+    magic: [u8; 2],
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    loop {
+        let message = match read_message(&mut stream, magic) {
+            Ok(message) => message,
+            // The client closed the connection
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut out = Vec::new();
+        {
+            let mut storage = storage
+                .lock()
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "Storage lock poisoned"))?;
+            match message {
+                // Accounts only carry a modification counter rather than a timestamp, so only
+                // requests for every account regardless of age can be answered
+                Message::FrontierReq(ref req) if req.age != u32::max_value() => {
+                    return Err(invalid_data("Unsupported frontier age"))
+                }
+                Message::FrontierReq(req) => write_frontiers(&mut *storage, req, &mut out),
+                Message::BulkPull(pull) => write_bulk_pull(&mut *storage, pull, &mut out),
+                m => return Err(invalid_data(m.message_type())),
+            }
+        }
+        stream.write_all(&out)?;
+    }
+}
+
+/// Serves frontier requests and bulk pulls from a ledger over TCP
+///
+/// Every connection is handled on its own thread, and can make any number of requests. Frontier
+/// requests must ask for every account, whatever its age. Once `MAX_CONNECTIONS` are open new ones
+/// are refused. Dropping the server closes every connection and waits for its thread.
+#[derive(Debug)]
+pub struct BootstrapServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BootstrapServer {
+    pub fn start<S, A>(addr: A, storage: Arc<Mutex<S>>, magic: [u8; 2]) -> io::Result<Self>
+    where
+        S: BlockStorage + Send + 'static,
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut connections = Connections::new(MAX_CONNECTIONS);
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let storage = storage.clone();
+                        // A connection that fails only affects that client
+                        let _ = connections.spawn(stream, move |stream| {
+                            let _ = serve(stream, &storage, magic);
+                        });
+                    }
+                }
+                connections.shutdown();
+            })
+        };
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for BootstrapServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake the listener up so it notices it has been stopped
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr.ip() {
+                IpAddr::V4(_) => Ipv4Addr::new(127, 0, 0, 1).into(),
+                IpAddr::V6(_) => Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1).into(),
+            });
+        }
+        let _ = TcpStream::connect(addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Fetches missing account chains from a `BootstrapServer`
+#[derive(Debug)]
+pub struct BootstrapClient {
+    stream: TcpStream,
+    magic: [u8; 2],
+}
+
+impl BootstrapClient {
+    pub fn connect<A: ToSocketAddrs>(addr: A, magic: [u8; 2]) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        Ok(Self { stream, magic })
+    }
+    fn request(&mut self, message: Message) -> io::Result<()> {
+        let bytes = message.to_bytes(self.magic).map_err(invalid_data)?;
+        self.stream.write_all(&bytes)
+    }
+    /// Fetch up to `count` accounts and their heads, in account order starting from `start`
+    pub fn frontiers(&mut self, start: PubKey, count: u32) -> io::Result<Vec<(PubKey, Hash)>> {
+        self.request(Message::FrontierReq(FrontierReq {
+            start,
+            age: u32::max_value(),
+            count,
+        }))?;
+        let mut frontiers = Vec::new();
+        loop {
+            let mut pair = [0; 64];
+            self.stream.read_exact(&mut pair)?;
+            if pair[..] == [0; 64][..] {
+                return Ok(frontiers);
+            }
+            let mut account = [0; 32];
+            let mut head = Hash::default();
+            account.copy_from_slice(&pair[..32]);
+            head.0.copy_from_slice(&pair[32..]);
+            frontiers.push((PubKey(account), head));
+        }
+    }
+    /// Fetch an account chain from its head, or from the block `start`, back to but not including
+    /// `end`, newest first
+    pub fn bulk_pull(&mut self, start: Hash, end: Hash) -> io::Result<Vec<Transaction>> {
+        self.request(Message::BulkPull(BulkPull { start, end }))?;
+        let mut blocks = Vec::new();
+        loop {
+            let mut tag = [0];
+            self.stream.read_exact(&mut tag)?;
+            let ty = BlockType::from_u8(tag[0]).map_err(invalid_data)?;
+            if ty == BlockType::NotABlock {
+                return Ok(blocks);
+            }
+            let mut body = vec![0; ty.size().ok_or_else(|| invalid_data(ty))?];
+            self.stream.read_exact(&mut body)?;
+            blocks.push(Transaction::deserialize(ty, &body).map_err(invalid_data)?);
+        }
+    }
+    /// Pull every account chain the server has that is ahead of ours, and import it
+    pub fn sync<S: BlockStorage + InsertVerified>(
+        &mut self,
+        importer: &mut Importer<S>,
+    ) -> io::Result<ImportSummary> {
+        let frontiers = self.frontiers(PubKey([0; 32]), u32::max_value())?;
+        let mut blocks = Vec::new();
+        for (account, head) in frontiers {
+            let end = {
+                let storage = importer.storage();
+                if storage.lookup(head).is_some() {
+                    continue;
+                }
+                storage.find_head(account).unwrap_or_default()
+            };
+            let mut chain = self.bulk_pull(Hash(account.0), end)?;
+            chain.reverse();
+            blocks.extend(chain);
+        }
+        Ok(importer.import(blocks))
+    }
+}
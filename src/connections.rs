@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// The threads serving the connections a TCP server has accepted
+///
+/// Finished connections are cleaned up each time a new one is accepted, and `shutdown` closes
+/// every connection that is still open and waits for its thread.
+#[derive(Debug)]
+pub struct Connections {
+    max: usize,
+    next_id: u64,
+    open: HashMap<u64, (TcpStream, JoinHandle<()>)>,
+    done_tx: Sender<u64>,
+    done_rx: Receiver<u64>,
+}
+
+impl Connections {
+    pub fn new(max: usize) -> Self {
+        let (done_tx, done_rx) = channel();
+        Self {
+            max,
+            next_id: 0,
+            open: HashMap::new(),
+            done_tx,
+            done_rx,
+        }
+    }
+    /// Serve a connection on its own thread
+    ///
+    /// Returns `false` without serving the connection if `max` connections are open already, the
+    /// stream is closed once it is dropped.
+    pub fn spawn<F>(&mut self, stream: TcpStream, serve: F) -> io::Result<bool>
+    where
+        F: FnOnce(TcpStream) + Send + 'static,
+    {
+        self.reap();
+        if self.open.len() >= self.max {
+            return Ok(false);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let handle = stream.try_clone()?;
+        let closer = stream.try_clone()?;
+        let done = self.done_tx.clone();
+        let thread = thread::spawn(move || {
+            serve(stream);
+            // The other handles would keep the connection open until it is cleaned up
+            let _ = closer.shutdown(Shutdown::Both);
+            let _ = done.send(id);
+        });
+        self.open.insert(id, (handle, thread));
+        Ok(true)
+    }
+    /// Join the threads of connections that have finished
+    fn reap(&mut self) {
+        while let Ok(id) = self.done_rx.try_recv() {
+            if let Some((_, thread)) = self.open.remove(&id) {
+                let _ = thread.join();
+            }
+        }
+    }
+    /// Close every open connection and wait for its thread to finish
+    pub fn shutdown(&mut self) {
+        for (_, (stream, thread)) in self.open.drain() {
+            let _ = stream.shutdown(Shutdown::Both);
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use byteorder::{ByteOrder, LE};
+use blake2::Blake2b;
+use blake2::digest::{Input, VariableOutput};
+
+use blockstorage::{AccountInfo, BlockStorage, Pending, Storage};
+use blockstorage::sealed::InsertVerified;
+use transaction::Transaction;
+use types::{Balance, Hash, PubKey};
+use errors::Failure;
+use network::NetworkParams;
+use work::Difficulty;
+
+/// The operation a log record replays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    Insert = 0,
+    /// The root and winner of a resolved fork
+    Resolve = 1,
+    /// The hash of a rolled back transaction
+    Rollback = 2,
+    /// The hash of a cemented transaction
+    Cement = 3,
+}
+
+impl RecordKind {
+    fn from_u8(kind: u8) -> Option<RecordKind> {
+        match kind {
+            0 => Some(RecordKind::Insert),
+            1 => Some(RecordKind::Resolve),
+            2 => Some(RecordKind::Rollback),
+            3 => Some(RecordKind::Cement),
+            _ => None,
+        }
+    }
+}
+
+/// Kind, payload length
+const HEADER_SIZE: usize = 1 + 4;
+const CHECKSUM_SIZE: usize = 8;
+
+fn checksum(record: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let mut hash = Blake2b::new(CHECKSUM_SIZE).expect("Unreachable");
+    hash.process(record);
+    let mut bytes = [0; CHECKSUM_SIZE];
+    hash.variable_result(&mut bytes).expect("Unreachable");
+    bytes
+}
+
+/// A `BlockStorage` that persists every accepted transaction to an append-only log on disk
+///
+/// The log is replayed into an in-memory `Storage` when it is opened, which rebuilds the
+/// transaction, balance, head and unspent indices. Every record is checksummed, so a record torn
+/// by a crash is detected and truncated away, reopening at the last state that was fully written.
+/// A corrupt record anywhere else in the log can't be a torn write, so opening fails instead.
+#[derive(Debug)]
+pub struct DiskStorage {
+    storage: Storage,
+    log: File,
+}
+
+impl DiskStorage {
+    /// Open the live ledger stored at `path`, creating it if it doesn't exist
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_with(path, Storage::new())
+    }
+    /// Open the ledger stored at `path`, replaying it on top of `storage`
+    ///
+    /// `storage` should be freshly created, containing only the genesis block the log was
+    /// written against.
+    pub fn open_with<P: AsRef<Path>>(path: P, mut storage: Storage) -> io::Result<Self> {
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut bytes = Vec::new();
+        log.read_to_end(&mut bytes)?;
+        let mut offset = 0;
+        while let Some((kind, payload)) = Self::read_record(&bytes[offset..])? {
+            match RecordKind::from_u8(kind) {
+                Some(RecordKind::Insert) => {
+                    let tx = Transaction::from_bytes(payload).map_err(Self::replay_error)?;
+                    match storage.insert(tx) {
+                        // Fork contenders are logged so their elections survive a restart
+                        Ok(()) | Err(Failure::Fork) => {}
+                        Err(e) => return Err(Self::replay_error(e)),
+                    }
+                }
+                Some(RecordKind::Resolve) if payload.len() == 64 => {
+                    let mut root = Hash::default();
+                    let mut winner = Hash::default();
+                    root.0.copy_from_slice(&payload[..32]);
+                    winner.0.copy_from_slice(&payload[32..]);
+                    storage
+                        .resolve_fork(root, winner)
+                        .map_err(Self::replay_error)?;
+                }
+                Some(RecordKind::Rollback) if payload.len() == 32 => {
+                    let mut hash = Hash::default();
+                    hash.0.copy_from_slice(payload);
+                    storage.rollback(hash).map_err(Self::replay_error)?;
+                }
+                Some(RecordKind::Cement) if payload.len() == 32 => {
+                    let mut hash = Hash::default();
+                    hash.0.copy_from_slice(payload);
+                    storage.cement(hash).map_err(Self::replay_error)?;
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Ledger log contains an unknown record kind: {}", kind),
+                    ))
+                }
+            }
+            offset += HEADER_SIZE + payload.len() + CHECKSUM_SIZE;
+        }
+        if offset != bytes.len() {
+            // The last record was only partially written
+            log.set_len(offset as u64)?;
+            log.sync_all()?;
+        }
+        Ok(Self { storage, log })
+    }
+    /// Read the record at the start of `bytes`, or `None` if it was torn by a crash
+    ///
+    /// A record is torn if it is incomplete, or if it fails its checksum and is the last record
+    /// in the log. A record that fails its checksum with more data after it is an error.
+    fn read_record(bytes: &[u8]) -> io::Result<Option<(u8, &[u8])>> {
+        if bytes.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let len = LE::read_u32(&bytes[1..HEADER_SIZE]) as usize;
+        let end = HEADER_SIZE + len;
+        if bytes.len() < end + CHECKSUM_SIZE {
+            return Ok(None);
+        }
+        if checksum(&bytes[..end]) != bytes[end..end + CHECKSUM_SIZE] {
+            if bytes.len() == end + CHECKSUM_SIZE {
+                return Ok(None);
+            }
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Ledger log contains a corrupt record",
+            ));
+        }
+        Ok(Some((bytes[0], &bytes[HEADER_SIZE..end])))
+    }
+    fn replay_error(e: Failure) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Ledger log contains an invalid record: {:?}", e),
+        )
+    }
+    fn append(&mut self, kind: RecordKind, payload: &[u8]) -> io::Result<()> {
+        let mut record = Vec::with_capacity(HEADER_SIZE + payload.len() + CHECKSUM_SIZE);
+        record.push(kind as u8);
+        let mut len = [0; 4];
+        LE::write_u32(&mut len, payload.len() as u32);
+        record.extend_from_slice(&len);
+        record.extend_from_slice(payload);
+        let sum = checksum(&record);
+        record.extend_from_slice(&sum);
+        self.log.write_all(&record)?;
+        self.log.sync_data()
+    }
+    /// Insert a transaction whose signature has already been verified, e.x. by `verify_parallel`
+    pub(crate) fn insert_verified(&mut self, tx: Transaction) -> Result<(), Failure> {
+        self.insert_impl(tx, false)
+    }
+    /// Insert a transaction, only returning once it has been written to disk
+    ///
+    /// If writing the log fails the transaction remains in memory, but will be missing when the
+    /// ledger is reopened.
+    fn insert_impl(&mut self, tx: Transaction, verify_sig: bool) -> Result<(), Failure> {
+        let bytes = tx.to_bytes();
+        // Only new contenders are logged, so repeating a fork doesn't grow the log
+        let inserted = self.storage.insert_impl(tx, verify_sig)?;
+        self.append(RecordKind::Insert, &bytes)
+            .map_err(|_| Failure::Storage)?;
+        inserted.into_result()
+    }
+}
+
+impl BlockStorage for DiskStorage {
+    fn lookup(&mut self, hash: Hash) -> Option<&Transaction> {
+        self.storage.lookup(hash)
+    }
+    fn find_head(&mut self, pubkey: PubKey) -> Option<Hash> {
+        self.storage.find_head(pubkey)
+    }
+    fn find_successor(&mut self, hash: Hash) -> Option<Hash> {
+        self.storage.find_successor(hash)
+    }
+    fn find_balance(&mut self, hash: Hash) -> Option<Balance> {
+        self.storage.find_balance(hash)
+    }
+    fn is_unspent(&mut self, hash: Hash) -> bool {
+        self.storage.is_unspent(hash)
+    }
+    fn find_pending(&mut self, account: PubKey) -> Vec<(Hash, Pending)> {
+        self.storage.find_pending(account)
+    }
+    fn find_representative(&mut self, account: PubKey) -> Option<PubKey> {
+        self.storage.find_representative(account)
+    }
+    fn account_info(&mut self, account: PubKey) -> Option<AccountInfo> {
+        self.storage.account_info(account)
+    }
+    fn find_frontiers(&mut self, start: PubKey, count: usize) -> Vec<(PubKey, Hash)> {
+        self.storage.find_frontiers(start, count)
+    }
+    fn find_weight(&mut self, representative: PubKey) -> Balance {
+        self.storage.find_weight(representative)
+    }
+    fn difficulty(&self) -> Difficulty {
+        self.storage.difficulty()
+    }
+    fn network(&self) -> &NetworkParams {
+        self.storage.network()
+    }
+    fn find_height(&mut self, hash: Hash) -> Option<u64> {
+        self.storage.find_height(hash)
+    }
+    fn find_confirmation_height(&mut self, account: PubKey) -> u64 {
+        self.storage.find_confirmation_height(account)
+    }
+    fn find_forks(&mut self) -> Vec<Hash> {
+        self.storage.find_forks()
+    }
+    fn find_conflicts(&mut self, root: Hash) -> Vec<Transaction> {
+        self.storage.find_conflicts(root)
+    }
+    fn resolve_fork(&mut self, root: Hash, winner: Hash) -> Result<(), Failure> {
+        self.storage.resolve_fork(root, winner)?;
+        let mut payload = Vec::with_capacity(64);
+        payload.extend_from_slice(&root.0);
+        payload.extend_from_slice(&winner.0);
+        self.append(RecordKind::Resolve, &payload)
+            .map_err(|_| Failure::Storage)
+    }
+    fn rollback(&mut self, hash: Hash) -> Result<Vec<Transaction>, Failure> {
+        let removed = self.storage.rollback(hash)?;
+        self.append(RecordKind::Rollback, &hash.0)
+            .map_err(|_| Failure::Storage)?;
+        Ok(removed)
+    }
+    fn cement(&mut self, hash: Hash) -> Result<(), Failure> {
+        self.storage.cement(hash)?;
+        self.append(RecordKind::Cement, &hash.0)
+            .map_err(|_| Failure::Storage)
+    }
+    fn insert(&mut self, tx: Transaction) -> Result<(), Failure> {
+        self.insert_impl(tx, true)
+    }
+}
+
+impl InsertVerified for DiskStorage {
+    fn insert_verified(&mut self, tx: Transaction) -> Result<(), Failure> {
+        DiskStorage::insert_verified(self, tx)
+    }
+}
@@ -7,10 +7,10 @@ const LIVE_KEY: PubKey = PubKey([
     0xE8, 0x92, 0x08, 0xDD, 0x03, 0x8F, 0xBB, 0x26, 0x99, 0x87, 0x68, 0x96, 0x21, 0xD5, 0x22, 0x92,
     0xAE, 0x9C, 0x35, 0x94, 0x1A, 0x74, 0x84, 0x75, 0x6E, 0xCC, 0xED, 0x92, 0xA6, 0x50, 0x93, 0xBA,
 ]);
-const LIVE_SOURCE: Hash = [
+const LIVE_SOURCE: Hash = Hash([
     0xE8, 0x92, 0x08, 0xDD, 0x03, 0x8F, 0xBB, 0x26, 0x99, 0x87, 0x68, 0x96, 0x21, 0xD5, 0x22, 0x92,
     0xAE, 0x9C, 0x35, 0x94, 0x1A, 0x74, 0x84, 0x75, 0x6E, 0xCC, 0xED, 0x92, 0xA6, 0x50, 0x93, 0xBA,
-];
+]);
 const LIVE_WORK: Work = Work(0x62f05417dd3fb691);
 const LIVE_SIGNATURE: Signature = Signature([
     0x9F, 0x0C, 0x93, 0x3C, 0x8A, 0xDE, 0x00, 0x4D, 0x80, 0x8E, 0xA1, 0x98, 0x5F, 0xA7, 0x46, 0xA7,
@@ -32,9 +32,43 @@ fn test_live_sig() {
 #[test]
 fn test_live_work() {
     use transaction::RaiWork;
+    use work::Difficulty;
     let work: u64 = LIVE_BLOCK.work_validate().into();
     println!("{:X}", work);
-    LIVE_BLOCK.verify_work().unwrap();
+    LIVE_BLOCK.verify_work(Difficulty::LIVE.receive).unwrap();
+}
+
+const BETA_KEY: PubKey = PubKey([
+    0xA5, 0x9A, 0x47, 0xCC, 0x4F, 0x59, 0x3E, 0x75, 0xAE, 0x9A, 0xD6, 0x53, 0xFD, 0xA9, 0x35, 0x8E,
+    0x2F, 0x78, 0x98, 0xD9, 0xAC, 0xC8, 0xC6, 0x0E, 0x80, 0xD0, 0x49, 0x5C, 0xE2, 0x0F, 0xBA, 0x9F,
+]);
+const BETA_SOURCE: Hash = Hash([
+    0xA5, 0x9A, 0x47, 0xCC, 0x4F, 0x59, 0x3E, 0x75, 0xAE, 0x9A, 0xD6, 0x53, 0xFD, 0xA9, 0x35, 0x8E,
+    0x2F, 0x78, 0x98, 0xD9, 0xAC, 0xC8, 0xC6, 0x0E, 0x80, 0xD0, 0x49, 0x5C, 0xE2, 0x0F, 0xBA, 0x9F,
+]);
+const BETA_WORK: Work = Work(0x000000000f0aaeeb);
+const BETA_SIGNATURE: Signature = Signature([
+    0xA7, 0x26, 0x49, 0x0E, 0x33, 0x25, 0xE4, 0xFA, 0x59, 0xC1, 0xC9, 0x00, 0xD5, 0xB6, 0xEE, 0xBB,
+    0x15, 0xFE, 0x13, 0xD9, 0x9F, 0x49, 0xD4, 0x75, 0xB9, 0x3F, 0x0A, 0xAC, 0xC5, 0x63, 0x59, 0x29,
+    0xA0, 0x61, 0x4C, 0xF3, 0x89, 0x27, 0x64, 0xA0, 0x4D, 0x1C, 0x67, 0x32, 0xA0, 0xD7, 0x16, 0xFF,
+    0xEB, 0x25, 0x4D, 0x41, 0x54, 0xC6, 0xF5, 0x44, 0xD1, 0x1E, 0x66, 0x30, 0xF2, 0x01, 0x45, 0x0B,
+]);
+pub const BETA_BLOCK: OpenTransaction = OpenTransaction {
+    account: BETA_KEY,
+    source: BETA_SOURCE,
+    representative: BETA_KEY,
+    work: BETA_WORK,
+    signature: BETA_SIGNATURE,
+};
+#[test]
+fn test_beta_sig() {
+    BETA_BLOCK.verify_sig().unwrap()
+}
+#[test]
+fn test_beta_work() {
+    use transaction::RaiWork;
+    use work::Difficulty;
+    BETA_BLOCK.verify_work(Difficulty::LIVE.receive).unwrap();
 }
 
 pub(crate) const TEST_PRIVATE_KEY: [u8; 32] = [
@@ -45,10 +79,10 @@ const TEST_KEY: PubKey = PubKey([
     0xB0, 0x31, 0x1E, 0xA5, 0x57, 0x08, 0xD6, 0xA5, 0x3C, 0x75, 0xCD, 0xBF, 0x88, 0x30, 0x02, 0x59,
     0xC6, 0xD0, 0x18, 0x52, 0x2F, 0xE3, 0xD4, 0xD0, 0xA2, 0x42, 0xE4, 0x31, 0xF9, 0xE8, 0xB6, 0xD0,
 ]);
-const TEST_SOURCE: Hash = [
+const TEST_SOURCE: Hash = Hash([
     0xB0, 0x31, 0x1E, 0xA5, 0x57, 0x08, 0xD6, 0xA5, 0x3C, 0x75, 0xCD, 0xBF, 0x88, 0x30, 0x02, 0x59,
     0xC6, 0xD0, 0x18, 0x52, 0x2F, 0xE3, 0xD4, 0xD0, 0xA2, 0x42, 0xE4, 0x31, 0xF9, 0xE8, 0xB6, 0xD0,
-];
+]);
 const TEST_WORK: Work = Work(0x9680625b39d3363d);
 const TEST_SIGNATURE: Signature = Signature([
     0xEC, 0xDA, 0x91, 0x43, 0x73, 0xA2, 0xF0, 0xCA, 0x12, 0x96, 0x47, 0x5B, 0xAE, 0xE4, 0x05, 0x00,
@@ -78,7 +112,8 @@ fn test_test_sig() {
 #[test]
 fn test_test_work() {
     use transaction::RaiWork;
+    use work::Difficulty;
     let work: u64 = TEST_BLOCK.work_validate().into();
     println!("{:X}", work);
-    TEST_BLOCK.verify_work().unwrap();
+    TEST_BLOCK.verify_work(Difficulty::LIVE.receive).unwrap();
 }
@@ -0,0 +1,110 @@
+use blockstorage::BlockStorage;
+use transaction::Transaction;
+use types::{Balance, Hash, PubKey};
+
+/// The order to walk an account's chain in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// From the head back to the open block
+    Backward,
+    /// From the open block up to the head
+    Forward,
+}
+
+/// How a transaction changed its account's balance
+///
+/// Balances use the full range of a `u128`, so the change is kept as a sign and a magnitude.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delta {
+    Credit(Balance),
+    Debit(Balance),
+    Unchanged,
+}
+
+impl Delta {
+    pub fn between(before: Balance, after: Balance) -> Delta {
+        if after > before {
+            Delta::Credit(after - before)
+        } else if after < before {
+            Delta::Debit(before - after)
+        } else {
+            Delta::Unchanged
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub hash: Hash,
+    pub tx: Transaction,
+    /// The balance of the account after this transaction
+    pub balance: Balance,
+    pub delta: Delta,
+}
+
+/// Iterates over the transactions in an account's chain
+#[derive(Debug)]
+pub struct History<'a, S: BlockStorage + 'a> {
+    storage: &'a mut S,
+    direction: Direction,
+    next: Option<Hash>,
+}
+
+impl<'a, S: BlockStorage> History<'a, S> {
+    /// Walk an account's chain starting at `start`, or at the head or open block if it is `None`
+    ///
+    /// If `start` doesn't belong to `account` the history is empty.
+    pub fn new(
+        storage: &'a mut S,
+        account: PubKey,
+        direction: Direction,
+        start: Option<Hash>,
+    ) -> Self {
+        let next = match (start, storage.account_info(account)) {
+            (Some(start), Some(_)) if storage.find_key(start) == Some(account) => Some(start),
+            (None, Some(info)) => match direction {
+                Direction::Backward => Some(info.head),
+                Direction::Forward => Some(info.open),
+            },
+            _ => None,
+        };
+        Self {
+            storage,
+            direction,
+            next,
+        }
+    }
+    /// The transaction that will be returned next
+    pub fn peek(&self) -> Option<Hash> {
+        self.next
+    }
+    /// Take up to `count` transactions, along with the hash to start the next page from
+    pub fn page(&mut self, count: usize) -> (Vec<HistoryEntry>, Option<Hash>) {
+        let entries = self.by_ref().take(count).collect();
+        (entries, self.peek())
+    }
+}
+
+impl<'a, S: BlockStorage> Iterator for History<'a, S> {
+    type Item = HistoryEntry;
+    fn next(&mut self) -> Option<HistoryEntry> {
+        let hash = self.next.take()?;
+        let tx = self.storage.lookup(hash)?.clone();
+        let previous = tx.previous();
+        let balance = self.storage.find_balance(hash)?;
+        let prev_bal = match previous {
+            Some(p) => self.storage.find_balance(p)?,
+            None => Balance(0),
+        };
+        self.next = match self.direction {
+            Direction::Backward => previous,
+            Direction::Forward => self.storage.find_successor(hash),
+        };
+        Some(HistoryEntry {
+            hash,
+            tx,
+            balance,
+            delta: Delta::between(prev_bal, balance),
+        })
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use blockstorage::BlockStorage;
+use blockstorage::sealed::InsertVerified;
+use transaction::{check_parallel, verify_parallel, RaiHash, RaiWork, RaiWorkImpl, Transaction};
+use types::Hash;
+use errors::Failure;
+use work::work_hash;
+
+/// The most transactions an `Importer` keeps waiting for a dependency by default
+pub const MAX_UNCHECKED: usize = 65536;
+
+/// The outcome of importing a batch of transactions
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Transactions added to the ledger, including earlier unchecked ones that were unblocked
+    pub inserted: usize,
+    /// Transactions left in the unchecked table, waiting for a dependency
+    pub unchecked: usize,
+    /// Transactions that were refused, along with why
+    pub rejected: Vec<(Hash, Failure)>,
+}
+
+/// Imports transactions that can arrive in any order, such as during a bootstrap
+///
+/// A transaction whose previous block or source is missing is parked in an unchecked table, keyed
+/// by the hash it is waiting for, and inserted as soon as that hash is imported. Once the table
+/// is full the oldest transactions are dropped, they can be fetched again later. Work and
+/// signatures are checked for a whole batch in parallel before anything touches the ledger, and
+/// everything a newly imported transaction unblocks is checked together in one more batch.
+#[derive(Debug)]
+pub struct Importer<S: BlockStorage> {
+    storage: S,
+    /// Transactions waiting for a dependency, whether their signature has been verified, and when
+    /// they were added
+    unchecked: HashMap<Hash, HashMap<Hash, (Transaction, bool, u64)>>,
+    /// The dependency and hash of each unchecked transaction, oldest first
+    order: BTreeMap<u64, (Hash, Hash)>,
+    next_seq: u64,
+    max_unchecked: usize,
+    threads: usize,
+}
+
+impl<S: BlockStorage + InsertVerified> Importer<S> {
+    pub fn new(storage: S, threads: usize) -> Self {
+        Self::with_max_unchecked(storage, threads, MAX_UNCHECKED)
+    }
+    pub fn with_max_unchecked(storage: S, threads: usize, max_unchecked: usize) -> Self {
+        Self {
+            storage,
+            unchecked: HashMap::new(),
+            order: BTreeMap::new(),
+            next_seq: 0,
+            max_unchecked,
+            threads: threads.max(1),
+        }
+    }
+    pub fn storage(&mut self) -> &mut S {
+        &mut self.storage
+    }
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+    /// The number of transactions waiting for a dependency
+    pub fn unchecked_len(&self) -> usize {
+        self.order.len()
+    }
+    /// The hashes that unchecked transactions are waiting for
+    pub fn find_missing(&self) -> Vec<Hash> {
+        self.unchecked.keys().cloned().collect()
+    }
+    /// Import a batch of transactions in any order
+    pub fn import(&mut self, txs: Vec<Transaction>) -> ImportSummary {
+        let mut summary = ImportSummary::default();
+        // Work is checked against the lowest threshold here, the ledger checks the exact one
+        let threshold = self.storage.difficulty().lowest();
+        let jobs = txs.iter()
+            .enumerate()
+            .map(|(i, tx)| (i, (tx.work_root(), tx.work_value())))
+            .collect();
+        let mut failed: HashMap<usize, Failure> =
+            check_parallel(jobs, self.threads, move |(root, work)| {
+                if work_hash(&root.0, work).verify_threshold(threshold) {
+                    Ok(())
+                } else {
+                    Err(Failure::Work)
+                }
+            }).into_iter()
+                .collect();
+        // Signatures whose signer is missing are checked when the transaction is inserted
+        let mut unverified = HashSet::new();
+        for (i, e) in verify_parallel(&mut self.storage, &txs, self.threads) {
+            match e {
+                Failure::Missing => {
+                    unverified.insert(i);
+                }
+                e => {
+                    failed.entry(i).or_insert(e);
+                }
+            }
+        }
+        let mut queue = VecDeque::with_capacity(txs.len());
+        for (i, tx) in txs.into_iter().enumerate() {
+            match failed.remove(&i) {
+                Some(e) => summary.rejected.push((tx.hash(), e)),
+                None => queue.push_back((tx, !unverified.contains(&i))),
+            }
+        }
+        let mut unblocked = Vec::new();
+        loop {
+            while let Some((tx, verified)) = queue.pop_front() {
+                let hash = tx.hash();
+                if let Some(missing) = find_dependency(&mut self.storage, &tx) {
+                    self.park(missing, hash, tx, verified);
+                    continue;
+                }
+                let result = if verified {
+                    self.storage.insert_verified(tx)
+                } else {
+                    self.storage.insert(tx)
+                };
+                match result {
+                    Ok(()) => {
+                        summary.inserted += 1;
+                        unblocked.push(hash);
+                    }
+                    Err(e) => summary.rejected.push((hash, e)),
+                }
+            }
+            if unblocked.is_empty() {
+                break;
+            }
+            let chain = self.take_chain(unblocked.drain(..).collect());
+            queue.extend(self.verify_chain(chain, &mut summary));
+        }
+        summary.unchecked = self.unchecked_len();
+        summary
+    }
+    /// Add a transaction to the unchecked table, dropping the oldest ones if it is full
+    fn park(&mut self, missing: Hash, hash: Hash, tx: Transaction, verified: bool) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let old = self.unchecked
+            .entry(missing)
+            .or_insert_with(HashMap::new)
+            .insert(hash, (tx, verified, seq));
+        if let Some((_, _, old)) = old {
+            self.order.remove(&old);
+        }
+        self.order.insert(seq, (missing, hash));
+        while self.order.len() > self.max_unchecked {
+            let oldest = *self.order.keys().next().expect("Unreachable");
+            let (missing, hash) = self.order.remove(&oldest).expect("Unreachable");
+            let empty = match self.unchecked.get_mut(&missing) {
+                Some(waiting) => {
+                    waiting.remove(&hash);
+                    waiting.is_empty()
+                }
+                None => false,
+            };
+            if empty {
+                self.unchecked.remove(&missing);
+            }
+        }
+    }
+    /// Remove everything waiting on these hashes from the unchecked table, along with everything
+    /// waiting on those in turn, dependencies first
+    fn take_chain(&mut self, hashes: Vec<Hash>) -> Vec<(Transaction, bool)> {
+        let mut pending: VecDeque<Hash> = hashes.into_iter().collect();
+        let mut chain = Vec::new();
+        while let Some(hash) = pending.pop_front() {
+            if let Some(waiting) = self.unchecked.remove(&hash) {
+                for (hash, (tx, verified, seq)) in waiting {
+                    self.order.remove(&seq);
+                    pending.push_back(hash);
+                    chain.push((tx, verified));
+                }
+            }
+        }
+        chain
+    }
+    /// Verify the signatures in an unblocked chain that couldn't be checked before, in one batch
+    fn verify_chain(
+        &mut self,
+        chain: Vec<(Transaction, bool)>,
+        summary: &mut ImportSummary,
+    ) -> Vec<(Transaction, bool)> {
+        let unverified: Vec<usize> = (0..chain.len()).filter(|&i| !chain[i].1).collect();
+        let txs: Vec<Transaction> = unverified.iter().map(|&i| chain[i].0.clone()).collect();
+        let mut failed = HashMap::new();
+        for (j, e) in verify_parallel(&mut self.storage, &txs, self.threads) {
+            failed.insert(unverified[j], e);
+        }
+        let mut checked = Vec::with_capacity(chain.len());
+        for (i, (tx, _)) in chain.into_iter().enumerate() {
+            match failed.remove(&i) {
+                // The signer is still missing, so the ledger checks it on insertion
+                Some(Failure::Missing) => checked.push((tx, false)),
+                Some(e) => summary.rejected.push((tx.hash(), e)),
+                None => checked.push((tx, true)),
+            }
+        }
+        checked
+    }
+}
+
+/// Find a transaction this one depends on that isn't in the ledger yet
+fn find_dependency<S: BlockStorage>(storage: &mut S, tx: &Transaction) -> Option<Hash> {
+    use transaction::Transaction::*;
+    if let Some(previous) = tx.previous() {
+        if storage.lookup(previous).is_none() {
+            return Some(previous);
+        }
+    }
+    let source = match tx {
+        &Open(ref o) => o.source,
+        &Receive(ref r) => r.source,
+        &State(ref s) if s.is_open() => s.link,
+        &State(ref s) => {
+            // Only state blocks that increase the balance depend on their link
+            let prev_bal = storage.find_balance(s.previous)?;
+            if s.balance <= prev_bal {
+                return None;
+            }
+            s.link
+        }
+        &Send(_) | &Change(_) => return None,
+    };
+    match storage.lookup(source) {
+        Some(_) => None,
+        None => Some(source),
+    }
+}
@@ -3,22 +3,38 @@ extern crate blake2;
 extern crate byteorder;
 extern crate ed25519_dalek;
 extern crate rand;
+#[macro_use]
+extern crate serde_json;
 
 #[cfg(test)]
 mod tests;
 mod genesis;
-mod transaction;
-mod types;
-mod blockstorage;
-mod work;
-mod errors {
+mod connections;
+pub mod network;
+pub mod node;
+pub mod peers;
+pub mod protocol;
+pub mod rpc;
+pub mod transaction;
+pub mod types;
+pub mod serialize;
+pub mod blockstorage;
+pub mod bootstrap;
+pub mod diskstorage;
+pub mod history;
+pub mod import;
+pub mod work;
+pub mod wallet;
+pub mod vote;
+pub mod errors {
     #[derive(Debug, Clone, PartialEq, Eq)]
     pub enum Failure {
         /// The transaction is already recorded
         Duplicate,
         /// The signature is invalid
         Signature,
-        /// The transaction's parent is not the head of the owner's account
+        /// The transaction's parent is not the head of the owner's account, the transaction has
+        /// been kept as a contender for its position unless too many contenders are kept already
         Fork,
         /// The provided PoW is invalid
         Work,
@@ -30,10 +46,52 @@ mod errors {
         OverSend,
         /// A block this transaction references is missing
         Missing,
+        /// The transaction has been confirmed, so it can't be rolled back or replaced
+        Confirmed,
         /// This transaction is structurally invalid, e.x. an Open block that references a receive
         /// block or a change block as its source
         Invalid,
+        /// A serialized transaction is truncated, has trailing bytes, or has an unknown type tag
+        Malformed,
+        /// The transaction was accepted but could not be persisted by the storage backend
+        Storage,
         /// This error should not happen, if it does there is a bug
         Unreachable,
     }
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum HexError {
+        /// The string is not exactly two characters for every byte
+        Length,
+        /// The string contains a character that isn't a hex digit
+        Character,
+    }
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum AddressError {
+        /// The address doesn't start with `xrb_` or `nano_`
+        Prefix,
+        /// The address is not 60 characters long after the prefix
+        Length,
+        /// The address contains a character outside of the base32 alphabet, or encodes more than
+        /// 256 bits
+        Character,
+        /// The checksum doesn't match the encoded account
+        Checksum,
+    }
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ProtocolError {
+        /// The message doesn't start with the magic bytes of this network
+        Magic,
+        /// The sender's protocol version is older than the oldest one supported
+        Version,
+        /// The message type is unknown or unsupported
+        MessageType,
+        /// The header's extensions don't describe a valid body, e.x. an unknown block type
+        Extensions,
+        /// The message ended before its body was complete
+        Truncated,
+        /// The message has bytes past the end of its body
+        Trailing,
+        /// The message lists more hashes than fit in its header, see `protocol::MAX_HASHES`
+        TooMany,
+    }
 }
@@ -0,0 +1,83 @@
+use ed25519_dalek as ed25519;
+
+use genesis;
+use transaction::OpenTransaction;
+use types::{Balance, Hash, PubKey};
+use errors::AddressError;
+use work::{compute_work, Difficulty};
+
+/// Everything that distinguishes one ledger from another
+#[derive(Debug, Clone)]
+pub struct NetworkParams {
+    /// The block that opens the genesis account, it sources itself
+    pub genesis: OpenTransaction,
+    /// The balance of the genesis account, which is every unit that will ever exist
+    pub genesis_balance: Balance,
+    pub difficulty: Difficulty,
+    /// The prefix of human readable account addresses, see `to_address` and `from_address`
+    pub address_prefix: &'static str,
+    /// The first two bytes of every message sent between nodes on this network
+    pub magic: [u8; 2],
+}
+
+impl NetworkParams {
+    pub fn live() -> Self {
+        Self {
+            genesis: genesis::LIVE_BLOCK,
+            genesis_balance: genesis::BALANCE,
+            difficulty: Difficulty::LIVE,
+            address_prefix: "xrb_",
+            magic: *b"RC",
+        }
+    }
+    /// The public beta network, which uses the live thresholds
+    pub fn beta() -> Self {
+        Self {
+            genesis: genesis::BETA_BLOCK,
+            genesis_balance: genesis::BALANCE,
+            difficulty: Difficulty::LIVE,
+            address_prefix: "xrb_",
+            magic: *b"RB",
+        }
+    }
+    /// The network used by tests, its genesis key is publicly known
+    pub fn test() -> Self {
+        Self {
+            genesis: genesis::TEST_BLOCK,
+            genesis_balance: genesis::BALANCE,
+            difficulty: Difficulty::TEST,
+            address_prefix: "xrb_",
+            magic: *b"RA",
+        }
+    }
+    /// Create a new private network whose genesis account is owned by `key`
+    pub fn custom(key: &ed25519::Keypair, balance: Balance, difficulty: Difficulty) -> Self {
+        Self {
+            genesis: mint_genesis(key, difficulty),
+            genesis_balance: balance,
+            difficulty,
+            address_prefix: "xrb_",
+            magic: *b"RA",
+        }
+    }
+    /// Encode an account as an address on this network
+    pub fn to_address(&self, account: PubKey) -> String {
+        account.to_address_with_prefix(self.address_prefix)
+    }
+    /// Parse an address on this network, networks using `xrb_` also accept `nano_`
+    pub fn from_address(&self, address: &str) -> Result<PubKey, AddressError> {
+        if self.address_prefix == "xrb_" {
+            return PubKey::from_address(address);
+        }
+        PubKey::from_address_with_prefix(address, self.address_prefix)
+    }
+}
+
+/// Create a genesis block for the account owned by `key`
+///
+/// Like the live genesis, the block uses its own account as its source and representative.
+pub fn mint_genesis(key: &ed25519::Keypair, difficulty: Difficulty) -> OpenTransaction {
+    let mut genesis = OpenTransaction::new_without_work(key, Hash(key.public.to_bytes()), None);
+    genesis.work = compute_work(&genesis, difficulty.receive);
+    genesis
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use ed25519_dalek as ed25519;
+use blake2::Blake2b;
+use rand::{random, thread_rng};
+
+use blockstorage::BlockStorage;
+use peers::PeerTable;
+use protocol::{Message, NodeIdHandshake, KEEPALIVE_PEERS, PROTOCOL_VERSION};
+use transaction::Transaction;
+use types::PubKey;
+use errors::Failure;
+
+/// The cookie we sent each peer that hasn't proven its node ID yet
+type Cookies = Mutex<HashMap<SocketAddr, [u8; 32]>>;
+
+/// Large enough for any message, the biggest is a publish of a state block
+const MAX_DATAGRAM: usize = 512;
+/// How often the receiving thread checks whether the node has been stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A node that exchanges live blocks with its peers over UDP
+///
+/// Published blocks are inserted into the ledger, and every block that is accepted is flooded to
+/// all other peers. Blocks the ledger already has are not flooded again, so flooding stops once
+/// every node has a block. Anyone who sends a valid message becomes a peer, and is sent a keepalive
+/// so it learns about us too, along with a node_id_handshake asking it to prove its node ID. The
+/// peers listed in keepalives are only contacted by `keepalive`, a few at a time.
+#[derive(Debug)]
+pub struct Node<S: BlockStorage> {
+    socket: UdpSocket,
+    storage: Arc<Mutex<S>>,
+    peers: Arc<Mutex<PeerTable>>,
+    node_id: Arc<ed25519::Keypair>,
+    cookies: Arc<Cookies>,
+    magic: [u8; 2],
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<S: BlockStorage + Send + 'static> Node<S> {
+    pub fn start<A: ToSocketAddrs>(
+        addr: A,
+        storage: Arc<Mutex<S>>,
+        magic: [u8; 2],
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let peers = Arc::new(Mutex::new(PeerTable::default()));
+        let node_id = Arc::new(ed25519::Keypair::generate::<Blake2b>(&mut thread_rng()));
+        let cookies = Arc::new(Mutex::new(HashMap::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let socket = socket.try_clone()?;
+            let storage = storage.clone();
+            let peers = peers.clone();
+            let node_id = node_id.clone();
+            let cookies = cookies.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut buf = [0; MAX_DATAGRAM];
+                while !stop.load(Ordering::Relaxed) {
+                    let (len, from) = match socket.recv_from(&mut buf) {
+                        Ok(received) => received,
+                        Err(_) => continue,
+                    };
+                    let (header, message) = match Message::from_bytes(magic, &buf[..len]) {
+                        Ok(received) => received,
+                        Err(_) => continue,
+                    };
+                    let (is_new, known) = {
+                        let mut peers = peers.lock().expect("Unreachable");
+                        let is_new = !peers.contains(from);
+                        match message {
+                            Message::Keepalive(ref listed) => {
+                                peers.process_keepalive(from, header.version_using, listed)
+                            }
+                            _ => {
+                                peers.contact(from, header.version_using);
+                            }
+                        }
+                        let known = peers.contains(from);
+                        (is_new && known, known)
+                    };
+                    if is_new {
+                        send_keepalive(&socket, &peers, magic, from);
+                        send_query(&socket, &cookies, magic, from);
+                    }
+                    match message {
+                        Message::Publish(tx) => {
+                            let _ = process(&socket, &storage, &peers, magic, tx, Some(from));
+                        }
+                        // Only peers in the table are answered, like keepalives
+                        Message::NodeIdHandshake(handshake) if known => {
+                            handle_handshake(
+                                &socket, &peers, &cookies, &node_id, magic, handshake, from,
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+            })
+        };
+        Ok(Self {
+            socket,
+            storage,
+            peers,
+            node_id,
+            cookies,
+            magic,
+            stop,
+            thread: Some(thread),
+        })
+    }
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+    pub fn storage(&self) -> &Arc<Mutex<S>> {
+        &self.storage
+    }
+    /// The key this node proves its identity to peers with, generated when the node starts
+    pub fn node_id(&self) -> PubKey {
+        self.node_id.public.into()
+    }
+    /// Add a peer we haven't heard from yet, assuming it uses our protocol version
+    pub fn add_peer(&self, peer: SocketAddr) -> bool {
+        self.peers
+            .lock()
+            .expect("Unreachable")
+            .contact(peer, PROTOCOL_VERSION)
+    }
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        self.peers.lock().expect("Unreachable").endpoints()
+    }
+    pub fn peer_table(&self) -> &Arc<Mutex<PeerTable>> {
+        &self.peers
+    }
+    /// Send a keepalive to every peer and to a few of the candidates peers have told us about, and
+    /// ask peers that haven't proven their node ID yet for it
+    pub fn keepalive(&self) {
+        let local = self.local_addr().ok();
+        let (peers, candidates, unidentified) = {
+            let mut peers = self.peers.lock().expect("Unreachable");
+            let endpoints = peers.endpoints();
+            let candidates = peers.take_candidates(KEEPALIVE_PEERS);
+            let unidentified: Vec<SocketAddr> = endpoints
+                .iter()
+                .cloned()
+                .filter(|&e| peers.find_peer(e).map_or(false, |p| p.node_id.is_none()))
+                .collect();
+            (endpoints, candidates, unidentified)
+        };
+        // Forget the cookies of peers that are gone
+        self.cookies
+            .lock()
+            .expect("Unreachable")
+            .retain(|e, _| peers.contains(e));
+        for peer in peers.into_iter().chain(candidates) {
+            if Some(peer) != local {
+                send_keepalive(&self.socket, &self.peers, self.magic, peer);
+            }
+        }
+        for peer in unidentified {
+            send_query(&self.socket, &self.cookies, self.magic, peer);
+        }
+    }
+    /// Forget every peer that hasn't sent us anything since `cutoff`
+    pub fn purge(&self, cutoff: Instant) -> Vec<SocketAddr> {
+        let stale = self.peers.lock().expect("Unreachable").purge(cutoff);
+        let mut cookies = self.cookies.lock().expect("Unreachable");
+        for peer in &stale {
+            cookies.remove(peer);
+        }
+        stale
+    }
+    /// Insert a transaction into the ledger and flood it to every peer
+    pub fn publish(&self, tx: Transaction) -> Result<(), Failure> {
+        process(&self.socket, &self.storage, &self.peers, self.magic, tx, None)
+    }
+}
+
+impl<S: BlockStorage> Drop for Node<S> {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Tell a peer about the peers we have heard from most recently
+fn send_keepalive(socket: &UdpSocket, peers: &Mutex<PeerTable>, magic: [u8; 2], to: SocketAddr) {
+    let list = peers.lock().expect("Unreachable").list(KEEPALIVE_PEERS);
+    let bytes = Message::Keepalive(list).to_bytes(magic).expect("Unreachable");
+    let _ = socket.send_to(&bytes, to);
+}
+
+/// Ask a peer to prove its node ID by signing a new random cookie
+fn send_query(socket: &UdpSocket, cookies: &Cookies, magic: [u8; 2], to: SocketAddr) {
+    let cookie: [u8; 32] = random();
+    cookies.lock().expect("Unreachable").insert(to, cookie);
+    let query = Message::NodeIdHandshake(NodeIdHandshake {
+        query: Some(cookie),
+        response: None,
+    });
+    let _ = socket.send_to(&query.to_bytes(magic).expect("Unreachable"), to);
+}
+
+/// Record a peer's node ID if it signed the cookie we sent it, and answer its query
+fn handle_handshake(
+    socket: &UdpSocket,
+    peers: &Mutex<PeerTable>,
+    cookies: &Cookies,
+    node_id: &ed25519::Keypair,
+    magic: [u8; 2],
+    handshake: NodeIdHandshake,
+    from: SocketAddr,
+) {
+    if handshake.response.is_some() {
+        let verified = {
+            let mut cookies = cookies.lock().expect("Unreachable");
+            // A forged response leaves the cookie in place for the real one
+            let verified = cookies.get(&from).and_then(|c| handshake.verify(c).ok());
+            if verified.is_some() {
+                cookies.remove(&from);
+            }
+            verified
+        };
+        if let Some(id) = verified {
+            peers.lock().expect("Unreachable").set_node_id(from, id);
+        }
+    }
+    if let Some(ref cookie) = handshake.query {
+        let response = Message::NodeIdHandshake(NodeIdHandshake {
+            query: None,
+            response: Some(NodeIdHandshake::respond(node_id, cookie)),
+        });
+        let _ = socket.send_to(&response.to_bytes(magic).expect("Unreachable"), from);
+    }
+}
+
+/// Insert a transaction, and if it is new flood it to every peer except the one it came from
+fn process<S: BlockStorage>(
+    socket: &UdpSocket,
+    storage: &Mutex<S>,
+    peers: &Mutex<PeerTable>,
+    magic: [u8; 2],
+    tx: Transaction,
+    from: Option<SocketAddr>,
+) -> Result<(), Failure> {
+    let bytes = Message::Publish(tx.clone())
+        .to_bytes(magic)
+        .expect("Unreachable");
+    storage.lock().map_err(|_| Failure::Storage)?.insert(tx)?;
+    for peer in peers.lock().expect("Unreachable").endpoints() {
+        if Some(peer) != from {
+            // Delivery isn't guaranteed over UDP anyway, a failed send is the same as a lost one
+            let _ = socket.send_to(&bytes, peer);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use types::PubKey;
+
+/// The most endpoints a single IP address may have in a `PeerTable` by default
+pub const MAX_PEERS_PER_IP: usize = 10;
+/// The most endpoints heard about from keepalives that are kept waiting to be contacted
+pub const MAX_CANDIDATES: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub endpoint: SocketAddr,
+    /// When the peer last sent us a message
+    pub last_contact: Instant,
+    /// The protocol version the peer is using
+    pub version: u8,
+    /// The peer's node ID, once it has proven it with a node_id_handshake
+    pub node_id: Option<PubKey>,
+}
+
+/// The peers a node knows about
+///
+/// Peers are only added when they contact us directly. Endpoints we hear about from keepalives are
+/// kept as candidates for the caller to contact at its own pace, so a forged keepalive can neither
+/// fill the table nor have us send to every endpoint it lists. Once there are `MAX_CANDIDATES` the
+/// oldest candidates are dropped.
+#[derive(Debug, Clone)]
+pub struct PeerTable {
+    peers: HashMap<SocketAddr, Peer>,
+    candidates: VecDeque<SocketAddr>,
+    max_per_ip: usize,
+}
+
+impl Default for PeerTable {
+    fn default() -> Self {
+        Self::new(MAX_PEERS_PER_IP)
+    }
+}
+
+impl PeerTable {
+    pub fn new(max_per_ip: usize) -> Self {
+        Self {
+            peers: HashMap::new(),
+            candidates: VecDeque::new(),
+            max_per_ip,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+    pub fn contains(&self, endpoint: SocketAddr) -> bool {
+        self.peers.contains_key(&endpoint)
+    }
+    pub fn find_peer(&self, endpoint: SocketAddr) -> Option<&Peer> {
+        self.peers.get(&endpoint)
+    }
+    /// Whether another endpoint can be added for this peer's IP address
+    fn has_room(&self, endpoint: SocketAddr) -> bool {
+        let same_ip = self.peers
+            .keys()
+            .filter(|e| e.ip() == endpoint.ip())
+            .count();
+        same_ip < self.max_per_ip
+    }
+    /// Record a message from a peer, adding it if it is new
+    ///
+    /// Returns `false` if the peer is new but its IP address already has too many endpoints.
+    pub fn contact(&mut self, endpoint: SocketAddr, version: u8) -> bool {
+        if let Some(peer) = self.peers.get_mut(&endpoint) {
+            peer.last_contact = Instant::now();
+            peer.version = version;
+            return true;
+        }
+        if !self.has_room(endpoint) {
+            return false;
+        }
+        self.peers.insert(
+            endpoint,
+            Peer {
+                endpoint,
+                last_contact: Instant::now(),
+                version,
+                node_id: None,
+            },
+        );
+        true
+    }
+    /// Record a keepalive from a peer, keeping the endpoints it listed that are new as candidates
+    pub fn process_keepalive(&mut self, from: SocketAddr, version: u8, peers: &[SocketAddr]) {
+        self.contact(from, version);
+        for &peer in peers {
+            if peer != from && !peer.ip().is_unspecified() && peer.port() != 0
+                && !self.contains(peer) && !self.candidates.contains(&peer)
+            {
+                if self.candidates.len() >= MAX_CANDIDATES {
+                    self.candidates.pop_front();
+                }
+                self.candidates.push_back(peer);
+            }
+        }
+    }
+    /// Take up to `count` candidates that are still worth contacting, oldest first
+    pub fn take_candidates(&mut self, count: usize) -> Vec<SocketAddr> {
+        let mut taken = Vec::new();
+        while taken.len() < count {
+            match self.candidates.pop_front() {
+                Some(peer) => {
+                    if !self.contains(peer) && self.has_room(peer) {
+                        taken.push(peer);
+                    }
+                }
+                None => break,
+            }
+        }
+        taken
+    }
+    /// Set the node ID of a known peer, returning `false` if the peer is unknown
+    pub fn set_node_id(&mut self, endpoint: SocketAddr, node_id: PubKey) -> bool {
+        match self.peers.get_mut(&endpoint) {
+            Some(peer) => {
+                peer.node_id = Some(node_id);
+                true
+            }
+            None => false,
+        }
+    }
+    pub fn remove(&mut self, endpoint: SocketAddr) -> Option<Peer> {
+        self.peers.remove(&endpoint)
+    }
+    /// Remove every peer that hasn't contacted us since `cutoff`, returning their endpoints
+    pub fn purge(&mut self, cutoff: Instant) -> Vec<SocketAddr> {
+        let stale: Vec<SocketAddr> = self.peers
+            .values()
+            .filter(|p| p.last_contact < cutoff)
+            .map(|p| p.endpoint)
+            .collect();
+        for endpoint in &stale {
+            self.peers.remove(endpoint);
+        }
+        stale
+    }
+    /// Up to `count` endpoints, most recently contacted first
+    pub fn list(&self, count: usize) -> Vec<SocketAddr> {
+        let mut peers: Vec<&Peer> = self.peers.values().collect();
+        peers.sort_by(|a, b| b.last_contact.cmp(&a.last_contact));
+        peers.into_iter().take(count).map(|p| p.endpoint).collect()
+    }
+    /// Every endpoint, in no particular order
+    pub fn endpoints(&self) -> Vec<SocketAddr> {
+        self.peers.keys().cloned().collect()
+    }
+}
+
+#[test]
+fn test_peer_table() {
+    use std::thread;
+    use std::time::Duration;
+    let a: SocketAddr = "10.0.0.1:7075".parse().unwrap();
+    let b: SocketAddr = "10.0.0.1:7076".parse().unwrap();
+    let c: SocketAddr = "10.0.0.1:7077".parse().unwrap();
+    let d: SocketAddr = "10.0.0.2:7075".parse().unwrap();
+    let mut peers = PeerTable::new(2);
+    assert!(peers.contact(a, 12));
+    assert!(peers.contact(b, 13));
+    // A third endpoint on the same IP is refused, but another IP is fine
+    assert!(!peers.contact(c, 13));
+    assert!(peers.contact(d, 13));
+    assert_eq!(peers.len(), 3);
+    assert!(peers.contact(a, 13));
+    assert_eq!(peers.find_peer(a).unwrap().version, 13);
+    assert_eq!(peers.list(1), vec![a]);
+
+    assert!(peers.set_node_id(d, PubKey([1; 32])));
+    assert!(!peers.set_node_id(c, PubKey([1; 32])));
+    assert_eq!(peers.find_peer(d).unwrap().node_id, Some(PubKey([1; 32])));
+
+    let e: SocketAddr = "10.0.0.3:7075".parse().unwrap();
+    let f: SocketAddr = "10.0.0.3:7076".parse().unwrap();
+    let listed = [a, c, e, e, f, "[::]:0".parse().unwrap()];
+    peers.process_keepalive(d, 13, &listed);
+    // The IP of `c` has no room left, so it isn't worth contacting
+    assert_eq!(peers.take_candidates(1), vec![e]);
+    assert_eq!(peers.take_candidates(8), vec![f]);
+    assert!(peers.take_candidates(8).is_empty());
+
+    thread::sleep(Duration::from_millis(10));
+    let cutoff = Instant::now();
+    peers.contact(b, 13);
+    let mut stale = peers.purge(cutoff);
+    stale.sort();
+    assert_eq!(stale, vec![a, d]);
+    assert_eq!(peers.endpoints(), vec![b]);
+}
@@ -0,0 +1,600 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use ed25519_dalek as ed25519;
+use blake2::Blake2b;
+use byteorder::{ByteOrder, LE};
+
+use serialize::{BlockType, Reader};
+use transaction::{verify_signature, Transaction};
+use types::{Hash, PubKey, Signature};
+use vote::Vote;
+use errors::{Failure, ProtocolError};
+
+/// The protocol version this node speaks
+pub const PROTOCOL_VERSION: u8 = 13;
+/// The oldest protocol version whose messages can be decoded, votes by hash arrived in 12
+pub const PROTOCOL_VERSION_MIN: u8 = 12;
+/// The number of peers in a keepalive
+pub const KEEPALIVE_PEERS: usize = 8;
+/// The most hashes a vote or confirm_req can carry, the count is stored in four bits
+pub const MAX_HASHES: usize = 12;
+
+const BLOCK_TYPE_MASK: u16 = 0x0f00;
+const COUNT_MASK: u16 = 0xf000;
+/// Set in a node_id_handshake's extensions when it carries a query
+const QUERY_FLAG: u16 = 0x0001;
+/// Set in a node_id_handshake's extensions when it carries a response
+const RESPONSE_FLAG: u16 = 0x0002;
+/// An IPv6 address and a little-endian port
+const ENDPOINT_SIZE: usize = 16 + 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    Invalid = 0,
+    NotAType = 1,
+    Keepalive = 2,
+    Publish = 3,
+    ConfirmReq = 4,
+    ConfirmAck = 5,
+    BulkPull = 6,
+    BulkPush = 7,
+    FrontierReq = 8,
+    NodeIdHandshake = 10,
+}
+
+impl MessageType {
+    pub fn from_u8(tag: u8) -> Result<MessageType, ProtocolError> {
+        use protocol::MessageType::*;
+        match tag {
+            0 => Ok(Invalid),
+            1 => Ok(NotAType),
+            2 => Ok(Keepalive),
+            3 => Ok(Publish),
+            4 => Ok(ConfirmReq),
+            5 => Ok(ConfirmAck),
+            6 => Ok(BulkPull),
+            7 => Ok(BulkPush),
+            8 => Ok(FrontierReq),
+            10 => Ok(NodeIdHandshake),
+            _ => Err(ProtocolError::MessageType),
+        }
+    }
+}
+
+/// The header that starts every message
+///
+/// The extensions carry the block type of publish, confirm_req and confirm_ack messages in bits 8
+/// to 11, and the number of hashes they contain in bits 12 to 15. A node_id_handshake sets bit 0
+/// when it carries a query and bit 1 when it carries a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub magic: [u8; 2],
+    pub version_max: u8,
+    pub version_using: u8,
+    pub version_min: u8,
+    pub message_type: MessageType,
+    pub extensions: u16,
+}
+
+impl Header {
+    pub const SIZE: usize = 2 + 1 + 1 + 1 + 1 + 2;
+    pub fn new(magic: [u8; 2], message_type: MessageType) -> Self {
+        Self {
+            magic,
+            version_max: PROTOCOL_VERSION,
+            version_using: PROTOCOL_VERSION,
+            version_min: PROTOCOL_VERSION_MIN,
+            message_type,
+            extensions: 0,
+        }
+    }
+    pub fn block_type(&self) -> Result<BlockType, ProtocolError> {
+        BlockType::from_u8(((self.extensions & BLOCK_TYPE_MASK) >> 8) as u8)
+            .map_err(|_| ProtocolError::Extensions)
+    }
+    pub fn set_block_type(&mut self, ty: BlockType) {
+        self.extensions = (self.extensions & !BLOCK_TYPE_MASK) | ((ty as u16) << 8);
+    }
+    pub fn count(&self) -> usize {
+        ((self.extensions & COUNT_MASK) >> 12) as usize
+    }
+    pub fn set_count(&mut self, count: usize) {
+        self.extensions = (self.extensions & !COUNT_MASK) | ((count as u16) << 12 & COUNT_MASK);
+    }
+    pub fn serialize(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.magic);
+        out.push(self.version_max);
+        out.push(self.version_using);
+        out.push(self.version_min);
+        out.push(self.message_type as u8);
+        let mut extensions = [0; 2];
+        LE::write_u16(&mut extensions, self.extensions);
+        out.extend_from_slice(&extensions);
+    }
+    /// Parse a header from the start of `bytes`, checking it belongs to the network with `magic`
+    pub fn deserialize(magic: [u8; 2], bytes: &[u8]) -> Result<Header, ProtocolError> {
+        if bytes.len() < Header::SIZE {
+            return Err(ProtocolError::Truncated);
+        }
+        if bytes[..2] != magic {
+            return Err(ProtocolError::Magic);
+        }
+        let header = Header {
+            magic,
+            version_max: bytes[2],
+            version_using: bytes[3],
+            version_min: bytes[4],
+            message_type: MessageType::from_u8(bytes[5])?,
+            extensions: LE::read_u16(&bytes[6..8]),
+        };
+        if header.version_using < PROTOCOL_VERSION_MIN {
+            return Err(ProtocolError::Version);
+        }
+        Ok(header)
+    }
+    /// The size of the body that follows this header
+    pub fn body_size(&self) -> Result<usize, ProtocolError> {
+        use protocol::MessageType::*;
+        let block_size = || self.block_type()?.size().ok_or(ProtocolError::Extensions);
+        match self.message_type {
+            Keepalive => Ok(KEEPALIVE_PEERS * ENDPOINT_SIZE),
+            Publish => block_size(),
+            ConfirmReq => match self.block_type()? {
+                BlockType::NotABlock => Ok(self.count() * 64),
+                _ => block_size(),
+            },
+            ConfirmAck => match self.block_type()? {
+                BlockType::NotABlock => Ok(32 + 64 + 8 + self.count() * 32),
+                _ => Err(ProtocolError::Extensions),
+            },
+            BulkPull => Ok(32 + 32),
+            FrontierReq => Ok(32 + 4 + 4),
+            NodeIdHandshake => {
+                let query = if self.extensions & QUERY_FLAG != 0 { 32 } else { 0 };
+                let response = if self.extensions & RESPONSE_FLAG != 0 { 32 + 64 } else { 0 };
+                Ok(query + response)
+            }
+            Invalid | NotAType | BulkPush => Err(ProtocolError::MessageType),
+        }
+    }
+}
+
+/// What a confirm_req asks to be voted on
+#[derive(Debug, Clone)]
+pub enum ConfirmReq {
+    Block(Transaction),
+    /// Pairs of block hash and root, see `Transaction::root`
+    Hashes(Vec<(Hash, Hash)>),
+}
+
+/// Asks for the blocks of an account chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BulkPull {
+    /// An account to pull from its head, or a block hash to pull from that block
+    pub start: Hash,
+    /// The block to stop at, or zero to pull the whole chain
+    pub end: Hash,
+}
+
+/// Asks for the heads of accounts, in account order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrontierReq {
+    /// The first account to return
+    pub start: PubKey,
+    /// Only return accounts modified within this many seconds, `u32::max_value()` for all
+    pub age: u32,
+    /// The most accounts to return, `u32::max_value()` for all
+    pub count: u32,
+}
+
+/// Asks a peer to prove its node ID, and proves ours in return
+///
+/// The node ID is a key pair generated by each node, which lets peers recognise it whatever
+/// endpoint it uses. A response is the node ID and its signature of the cookie from the query.
+#[derive(Debug, Clone, Copy)]
+pub struct NodeIdHandshake {
+    /// A random cookie for the receiver to sign
+    pub query: Option<[u8; 32]>,
+    pub response: Option<(PubKey, Signature)>,
+}
+
+impl NodeIdHandshake {
+    /// Sign the cookie from a query with our node ID
+    pub fn respond(node_id: &ed25519::Keypair, cookie: &[u8; 32]) -> (PubKey, Signature) {
+        (node_id.public.into(), node_id.sign::<Blake2b>(cookie).into())
+    }
+    /// Check this handshake's response against the cookie we sent, returning the sender's node ID
+    pub fn verify(&self, cookie: &[u8; 32]) -> Result<PubKey, Failure> {
+        let (node_id, signature) = self.response.ok_or(Failure::Missing)?;
+        verify_signature(node_id, cookie, signature)?;
+        Ok(node_id)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    /// Peers the sender knows about, at most `KEEPALIVE_PEERS`
+    Keepalive(Vec<SocketAddr>),
+    Publish(Transaction),
+    ConfirmReq(ConfirmReq),
+    /// A vote, only votes by hash are supported
+    ConfirmAck(Vote),
+    BulkPull(BulkPull),
+    FrontierReq(FrontierReq),
+    NodeIdHandshake(NodeIdHandshake),
+}
+
+impl Message {
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            &Message::Keepalive(_) => MessageType::Keepalive,
+            &Message::Publish(_) => MessageType::Publish,
+            &Message::ConfirmReq(_) => MessageType::ConfirmReq,
+            &Message::ConfirmAck(_) => MessageType::ConfirmAck,
+            &Message::BulkPull(_) => MessageType::BulkPull,
+            &Message::FrontierReq(_) => MessageType::FrontierReq,
+            &Message::NodeIdHandshake(_) => MessageType::NodeIdHandshake,
+        }
+    }
+    /// Check that every list in the message fits in one message
+    ///
+    /// Hashes are never left out, since a vote's signature covers every one of them.
+    fn check_count(&self) -> Result<(), ProtocolError> {
+        let count = match self {
+            &Message::ConfirmReq(ConfirmReq::Hashes(ref pairs)) => pairs.len(),
+            &Message::ConfirmAck(ref vote) => vote.hashes.len(),
+            _ => 0,
+        };
+        if count > MAX_HASHES {
+            Err(ProtocolError::TooMany)
+        } else {
+            Ok(())
+        }
+    }
+    /// The header describing this message's body
+    pub fn header(&self, magic: [u8; 2]) -> Result<Header, ProtocolError> {
+        self.check_count()?;
+        let mut header = Header::new(magic, self.message_type());
+        match self {
+            &Message::Publish(ref tx) | &Message::ConfirmReq(ConfirmReq::Block(ref tx)) => {
+                header.set_block_type(tx.block_type())
+            }
+            &Message::ConfirmReq(ConfirmReq::Hashes(ref pairs)) => {
+                header.set_block_type(BlockType::NotABlock);
+                header.set_count(pairs.len());
+            }
+            &Message::ConfirmAck(ref vote) => {
+                header.set_block_type(BlockType::NotABlock);
+                header.set_count(vote.hashes.len());
+            }
+            &Message::NodeIdHandshake(ref handshake) => {
+                if handshake.query.is_some() {
+                    header.extensions |= QUERY_FLAG;
+                }
+                if handshake.response.is_some() {
+                    header.extensions |= RESPONSE_FLAG;
+                }
+            }
+            _ => {}
+        }
+        Ok(header)
+    }
+    /// Serialize this message's body
+    ///
+    /// Anything past `KEEPALIVE_PEERS` peers is left out, but more than `MAX_HASHES` hashes is an
+    /// error.
+    pub fn serialize(&self, out: &mut Vec<u8>) -> Result<(), ProtocolError> {
+        self.check_count()?;
+        match self {
+            &Message::Keepalive(ref peers) => {
+                for i in 0..KEEPALIVE_PEERS {
+                    // Unused slots are left as the unspecified address
+                    let unused = SocketAddr::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0).into(), 0);
+                    write_endpoint(out, peers.get(i).cloned().unwrap_or(unused));
+                }
+            }
+            &Message::Publish(ref tx) | &Message::ConfirmReq(ConfirmReq::Block(ref tx)) => {
+                tx.serialize(out)
+            }
+            &Message::ConfirmReq(ConfirmReq::Hashes(ref pairs)) => {
+                for &(hash, root) in pairs {
+                    out.extend_from_slice(&hash.0);
+                    out.extend_from_slice(&root.0);
+                }
+            }
+            &Message::ConfirmAck(ref vote) => {
+                out.extend_from_slice(vote.representative.as_ref());
+                out.extend_from_slice(&vote.signature.0);
+                let mut sequence = [0; 8];
+                LE::write_u64(&mut sequence, vote.sequence);
+                out.extend_from_slice(&sequence);
+                for hash in &vote.hashes {
+                    out.extend_from_slice(&hash.0);
+                }
+            }
+            &Message::BulkPull(ref pull) => {
+                out.extend_from_slice(&pull.start.0);
+                out.extend_from_slice(&pull.end.0);
+            }
+            &Message::FrontierReq(ref req) => {
+                out.extend_from_slice(req.start.as_ref());
+                let mut numbers = [0; 8];
+                LE::write_u32(&mut numbers[..4], req.age);
+                LE::write_u32(&mut numbers[4..], req.count);
+                out.extend_from_slice(&numbers);
+            }
+            &Message::NodeIdHandshake(ref handshake) => {
+                if let Some(ref cookie) = handshake.query {
+                    out.extend_from_slice(cookie);
+                }
+                if let Some((node_id, signature)) = handshake.response {
+                    out.extend_from_slice(node_id.as_ref());
+                    out.extend_from_slice(&signature.0);
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Serialize this message, prefixed with its header
+    pub fn to_bytes(&self, magic: [u8; 2]) -> Result<Vec<u8>, ProtocolError> {
+        let mut out = Vec::new();
+        self.header(magic)?.serialize(&mut out);
+        self.serialize(&mut out)?;
+        Ok(out)
+    }
+    /// Parse the body described by `header`, `body` must be exactly `header.body_size()` long
+    pub fn deserialize(header: &Header, body: &[u8]) -> Result<Message, ProtocolError> {
+        use protocol::MessageType::*;
+        let size = header.body_size()?;
+        if body.len() < size {
+            return Err(ProtocolError::Truncated);
+        }
+        if body.len() > size {
+            return Err(ProtocolError::Trailing);
+        }
+        let mut r = Reader::new(body);
+        let message = match header.message_type {
+            Keepalive => {
+                let mut peers = Vec::with_capacity(KEEPALIVE_PEERS);
+                for _ in 0..KEEPALIVE_PEERS {
+                    let peer = read_endpoint(r.take(ENDPOINT_SIZE).map_err(truncated)?);
+                    if peer.port() != 0 {
+                        peers.push(peer);
+                    }
+                }
+                Message::Keepalive(peers)
+            }
+            Publish => Message::Publish(read_block(header, body)?),
+            ConfirmReq => match header.block_type()? {
+                BlockType::NotABlock => {
+                    let mut pairs = Vec::with_capacity(header.count());
+                    for _ in 0..header.count() {
+                        pairs.push((r.hash().map_err(truncated)?, r.hash().map_err(truncated)?));
+                    }
+                    Message::ConfirmReq(self::ConfirmReq::Hashes(pairs))
+                }
+                _ => Message::ConfirmReq(self::ConfirmReq::Block(read_block(header, body)?)),
+            },
+            ConfirmAck => {
+                let representative = r.pubkey().map_err(truncated)?;
+                let signature = r.signature().map_err(truncated)?;
+                let sequence = LE::read_u64(r.take(8).map_err(truncated)?);
+                let mut hashes = Vec::with_capacity(header.count());
+                for _ in 0..header.count() {
+                    hashes.push(r.hash().map_err(truncated)?);
+                }
+                Message::ConfirmAck(Vote {
+                    representative,
+                    sequence,
+                    hashes,
+                    signature,
+                })
+            }
+            BulkPull => Message::BulkPull(self::BulkPull {
+                start: r.hash().map_err(truncated)?,
+                end: r.hash().map_err(truncated)?,
+            }),
+            FrontierReq => Message::FrontierReq(self::FrontierReq {
+                start: r.pubkey().map_err(truncated)?,
+                age: LE::read_u32(r.take(4).map_err(truncated)?),
+                count: LE::read_u32(r.take(4).map_err(truncated)?),
+            }),
+            NodeIdHandshake => {
+                let query = if header.extensions & QUERY_FLAG != 0 {
+                    let mut cookie = [0; 32];
+                    cookie.copy_from_slice(r.take(32).map_err(truncated)?);
+                    Some(cookie)
+                } else {
+                    None
+                };
+                let response = if header.extensions & RESPONSE_FLAG != 0 {
+                    Some((r.pubkey().map_err(truncated)?, r.signature().map_err(truncated)?))
+                } else {
+                    None
+                };
+                Message::NodeIdHandshake(self::NodeIdHandshake { query, response })
+            }
+            Invalid | NotAType | BulkPush => return Err(ProtocolError::MessageType),
+        };
+        Ok(message)
+    }
+    /// Parse a message prefixed with its header, `bytes` must contain exactly one message
+    pub fn from_bytes(magic: [u8; 2], bytes: &[u8]) -> Result<(Header, Message), ProtocolError> {
+        let header = Header::deserialize(magic, bytes)?;
+        let message = Message::deserialize(&header, &bytes[Header::SIZE..])?;
+        Ok((header, message))
+    }
+}
+
+fn truncated(_: Failure) -> ProtocolError {
+    ProtocolError::Truncated
+}
+
+fn read_block(header: &Header, body: &[u8]) -> Result<Transaction, ProtocolError> {
+    Transaction::deserialize(header.block_type()?, body).map_err(|_| ProtocolError::Extensions)
+}
+
+/// Write an address as 16 bytes of IPv6, with IPv4 addresses mapped, and a little-endian port
+fn write_endpoint(out: &mut Vec<u8>, addr: SocketAddr) {
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    };
+    out.extend_from_slice(&ip.octets());
+    let mut port = [0; 2];
+    LE::write_u16(&mut port, addr.port());
+    out.extend_from_slice(&port);
+}
+
+fn read_endpoint(bytes: &[u8]) -> SocketAddr {
+    let mut octets = [0; 16];
+    octets.copy_from_slice(&bytes[..16]);
+    let port = LE::read_u16(&bytes[16..]);
+    let ip = Ipv6Addr::from(octets);
+    if octets[..10] == [0; 10] && octets[10..12] == [0xff, 0xff] {
+        let v4 = Ipv4Addr::new(octets[12], octets[13], octets[14], octets[15]);
+        SocketAddr::new(v4.into(), port)
+    } else {
+        SocketAddrV6::new(ip, port, 0, 0).into()
+    }
+}
+
+#[test]
+fn test_header() {
+    let mut header = Header::new(*b"RC", MessageType::Publish);
+    header.set_block_type(BlockType::State);
+    let mut bytes = Vec::new();
+    header.serialize(&mut bytes);
+    assert_eq!(bytes, vec![b'R', b'C', 13, 13, 12, 3, 0x00, 0x06]);
+    assert_eq!(Header::deserialize(*b"RC", &bytes), Ok(header));
+    assert_eq!(header.body_size(), Ok(216));
+    assert_eq!(Header::deserialize(*b"RA", &bytes), Err(ProtocolError::Magic));
+    assert_eq!(Header::deserialize(*b"RC", &bytes[..7]), Err(ProtocolError::Truncated));
+    bytes[3] = 11;
+    assert_eq!(Header::deserialize(*b"RC", &bytes), Err(ProtocolError::Version));
+    bytes[3] = 13;
+    bytes[5] = 42;
+    assert_eq!(Header::deserialize(*b"RC", &bytes), Err(ProtocolError::MessageType));
+    // Block type 9 doesn't exist
+    bytes[5] = 3;
+    bytes[7] = 0x09;
+    let header = Header::deserialize(*b"RC", &bytes).unwrap();
+    assert_eq!(header.body_size(), Err(ProtocolError::Extensions));
+}
+
+#[test]
+fn test_messages() {
+    use genesis::TEST_BLOCK;
+    use transaction::RaiHash;
+    use types::Signature;
+    let magic = *b"RA";
+    let keepalive = Message::Keepalive(vec!["127.0.0.1:7075".parse().unwrap()]);
+    let bytes = keepalive.to_bytes(magic).unwrap();
+    assert_eq!(bytes.len(), Header::SIZE + 144);
+    assert_eq!(
+        &bytes[8..26],
+        &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 127, 0, 0, 1, 0xa3, 0x1b]
+    );
+    assert!(bytes[26..].iter().all(|&b| b == 0));
+    match Message::from_bytes(magic, &bytes).unwrap().1 {
+        Message::Keepalive(peers) => assert_eq!(peers, vec!["127.0.0.1:7075".parse().unwrap()]),
+        m => panic!("Unexpected message {:?}", m),
+    }
+    assert_eq!(
+        Message::from_bytes(magic, &bytes[..100]).unwrap_err(),
+        ProtocolError::Truncated
+    );
+    let mut long = bytes.clone();
+    long.push(0);
+    assert_eq!(Message::from_bytes(magic, &long).unwrap_err(), ProtocolError::Trailing);
+
+    let pull = Message::BulkPull(BulkPull {
+        start: Hash([1; 32]),
+        end: Hash([2; 32]),
+    });
+    let bytes = pull.to_bytes(magic).unwrap();
+    assert_eq!(&bytes[..8], &[b'R', b'A', 13, 13, 12, 6, 0, 0]);
+    assert_eq!(&bytes[8..40], &[1; 32]);
+    assert_eq!(&bytes[40..], &[2; 32]);
+
+    let req = Message::FrontierReq(FrontierReq {
+        start: PubKey([3; 32]),
+        age: 1,
+        count: 0x01020304,
+    });
+    let bytes = req.to_bytes(magic).unwrap();
+    assert_eq!(&bytes[40..], &[1, 0, 0, 0, 4, 3, 2, 1]);
+    match Message::from_bytes(magic, &bytes).unwrap().1 {
+        Message::FrontierReq(r) => assert_eq!(r.count, 0x01020304),
+        m => panic!("Unexpected message {:?}", m),
+    }
+
+    let vote = Vote {
+        representative: PubKey([4; 32]),
+        sequence: 5,
+        hashes: vec![Hash([6; 32]), Hash([7; 32])],
+        signature: Signature([8; 64]),
+    };
+    let bytes = Message::ConfirmAck(vote).to_bytes(magic).unwrap();
+    assert_eq!(&bytes[..8], &[b'R', b'A', 13, 13, 12, 5, 0x00, 0x21]);
+    assert_eq!(bytes.len(), Header::SIZE + 32 + 64 + 8 + 64);
+    assert_eq!(&bytes[104..112], &[5, 0, 0, 0, 0, 0, 0, 0]);
+    match Message::from_bytes(magic, &bytes).unwrap().1 {
+        Message::ConfirmAck(v) => {
+            assert_eq!(v.representative, PubKey([4; 32]));
+            assert_eq!(v.hashes, vec![Hash([6; 32]), Hash([7; 32])]);
+        }
+        m => panic!("Unexpected message {:?}", m),
+    }
+
+    let publish = Message::Publish(Transaction::Open(TEST_BLOCK));
+    let bytes = publish.to_bytes(magic).unwrap();
+    assert_eq!(&bytes[..8], &[b'R', b'A', 13, 13, 12, 3, 0x00, 0x04]);
+    assert_eq!(&bytes[8..], &Transaction::Open(TEST_BLOCK).to_bytes()[1..]);
+    match Message::from_bytes(magic, &bytes).unwrap().1 {
+        Message::Publish(tx) => assert_eq!(tx.hash(), TEST_BLOCK.hash()),
+        m => panic!("Unexpected message {:?}", m),
+    }
+
+    let pairs = vec![(Hash([9; 32]), Hash([10; 32]))];
+    let bytes = Message::ConfirmReq(ConfirmReq::Hashes(pairs.clone())).to_bytes(magic).unwrap();
+    assert_eq!(&bytes[6..8], &[0x00, 0x11]);
+    match Message::from_bytes(magic, &bytes).unwrap().1 {
+        Message::ConfirmReq(ConfirmReq::Hashes(p)) => assert_eq!(p, pairs),
+        m => panic!("Unexpected message {:?}", m),
+    }
+    // Hashes are never silently dropped to make a message fit
+    let pairs = vec![(Hash([9; 32]), Hash([10; 32])); MAX_HASHES + 1];
+    let req = Message::ConfirmReq(ConfirmReq::Hashes(pairs));
+    assert_eq!(req.to_bytes(magic), Err(ProtocolError::TooMany));
+    let vote = Vote {
+        representative: PubKey([4; 32]),
+        sequence: 5,
+        hashes: vec![Hash([6; 32]); MAX_HASHES + 1],
+        signature: Signature([8; 64]),
+    };
+    assert_eq!(Message::ConfirmAck(vote).to_bytes(magic), Err(ProtocolError::TooMany));
+
+    let node_id = ed25519::Keypair::generate::<Blake2b>(&mut ::rand::thread_rng());
+    let query = Message::NodeIdHandshake(NodeIdHandshake {
+        query: Some([11; 32]),
+        response: None,
+    });
+    let bytes = query.to_bytes(magic).unwrap();
+    assert_eq!(&bytes[5..8], &[10, 0x01, 0x00]);
+    assert_eq!(&bytes[8..], &[11; 32]);
+    let response = NodeIdHandshake {
+        query: Some([12; 32]),
+        response: Some(NodeIdHandshake::respond(&node_id, &[11; 32])),
+    };
+    let bytes = Message::NodeIdHandshake(response).to_bytes(magic).unwrap();
+    assert_eq!(&bytes[6..8], &[0x03, 0x00]);
+    assert_eq!(bytes.len(), Header::SIZE + 32 + 32 + 64);
+    match Message::from_bytes(magic, &bytes).unwrap().1 {
+        Message::NodeIdHandshake(h) => {
+            assert_eq!(h.query, Some([12; 32]));
+            assert_eq!(h.verify(&[11; 32]), Ok(node_id.public.into()));
+            assert_eq!(h.verify(&[12; 32]), Err(Failure::Signature));
+        }
+        m => panic!("Unexpected message {:?}", m),
+    }
+}
@@ -0,0 +1,620 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::{self, Map, Value};
+
+use blockstorage::BlockStorage;
+use connections::Connections;
+use history::{Delta, Direction, History};
+use transaction::{ChangeTransaction, OpenTransaction, ReceiveTransaction, SendTransaction,
+                  StateTransaction, Transaction, RaiHash};
+use types::{Balance, Hash, PubKey, Signature, Work};
+use errors::Failure;
+use network::NetworkParams;
+use work::{work_hash, Difficulty, WorkGenerator};
+
+/// The most a client may send in one request, headers included
+const MAX_REQUEST: u64 = 64 * 1024;
+/// How long the server waits for a client to send its request
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// The most connections an `RpcServer` serves at once
+const MAX_CONNECTIONS: usize = 8;
+/// The most work `work_generate` will do, as a multiple of the highest threshold
+const MAX_WORK_MULTIPLIER: f64 = 64.0;
+/// How long `work_generate` searches before giving up
+const WORK_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often `work_generate` checks whether it should give up
+const WORK_POLL: Duration = Duration::from_millis(10);
+
+/// An error message returned to the client
+type RpcResult = Result<Value, &'static str>;
+
+/// Serves Nano style RPC requests from a ledger, as JSON over HTTP
+///
+/// Every request is a POST whose body is an object with an `action` field, and every response is
+/// a JSON object, with an `error` field if the request failed. Only connections from the loopback
+/// interface are served, since `process` and `work_generate` let clients use the node's resources,
+/// and once `MAX_CONNECTIONS` are open new ones are refused. Dropping the server closes every
+/// connection, cancels any work being generated and waits for the connection threads.
+#[derive(Debug)]
+pub struct RpcServer {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RpcServer {
+    /// Start serving, using up to `threads` threads to generate work for each request
+    pub fn start<S, A>(addr: A, storage: Arc<Mutex<S>>, threads: usize) -> io::Result<Self>
+    where
+        S: BlockStorage + Send + 'static,
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut connections = Connections::new(MAX_CONNECTIONS);
+                for stream in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(_) => continue,
+                    };
+                    match stream.peer_addr() {
+                        Ok(peer) if peer.ip().is_loopback() => {}
+                        _ => continue,
+                    }
+                    let storage = storage.clone();
+                    let stop = stop.clone();
+                    // A connection that fails only affects that client
+                    let _ = connections.spawn(stream, move |stream| {
+                        let _ = serve(stream, &storage, threads, &stop);
+                    });
+                }
+                connections.shutdown();
+            })
+        };
+        Ok(Self {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake the listener up so it notices it has been stopped
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr.ip() {
+                IpAddr::V4(_) => Ipv4Addr::new(127, 0, 0, 1).into(),
+                IpAddr::V6(_) => Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1).into(),
+            });
+        }
+        let _ = TcpStream::connect(addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read the body of an HTTP POST request
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("POST ") {
+        return Err(invalid_data("Only POST requests are supported"));
+    }
+    let mut length = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = Some(value.trim().parse().map_err(|_| invalid_data("Bad length"))?);
+            }
+        }
+    }
+    let length: usize = length.ok_or_else(|| invalid_data("Missing length"))?;
+    // Checked before allocating, the reader's limit only applies once the body is read
+    if length as u64 > MAX_REQUEST {
+        return Err(invalid_data("Request too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+fn serve<S: BlockStorage>(
+    stream: TcpStream,
+    storage: &Mutex<S>,
+    threads: usize,
+    stop: &AtomicBool,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let request = read_request(&mut BufReader::new((&stream).take(MAX_REQUEST)));
+    let (status, response) = match request {
+        Ok(body) => ("200 OK", handle(storage, &body, threads, stop)),
+        Err(_) => ("400 Bad Request", json!({ "error": "Bad request" })),
+    };
+    let body = response.to_string();
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+/// Respond to the JSON body of a request
+fn handle<S: BlockStorage>(
+    storage: &Mutex<S>,
+    body: &[u8],
+    threads: usize,
+    stop: &AtomicBool,
+) -> Value {
+    let request: Value = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(_) => return json!({ "error": "Unable to parse JSON" }),
+    };
+    let result = match request["action"].as_str() {
+        // Work generation can take a while, so it mustn't hold the ledger
+        Some("work_generate") => {
+            let difficulty = match storage.lock() {
+                Ok(storage) => Ok(storage.difficulty()),
+                Err(_) => Err("Internal error"),
+            };
+            difficulty.and_then(|d| work_generate(&request, d, threads, stop))
+        }
+        Some(action) => match storage.lock() {
+            Ok(mut storage) => {
+                let storage = &mut *storage;
+                let network = &storage.network().clone();
+                match action {
+                    "account_balance" => account_balance(storage, network, &request),
+                    "account_info" => account_info(storage, network, &request),
+                    "account_history" => account_history(storage, network, &request),
+                    "block_info" => block_info(storage, network, &request),
+                    "pending" => pending(storage, network, &request),
+                    "process" => process(storage, network, &request),
+                    "work_validate" => work_validate(&request, storage.difficulty()),
+                    _ => Err("Unknown command"),
+                }
+            }
+            Err(_) => Err("Internal error"),
+        },
+        None => Err("Unknown command"),
+    };
+    result.unwrap_or_else(|e| json!({ "error": e }))
+}
+
+fn account_field(
+    network: &NetworkParams,
+    request: &Value,
+    name: &str,
+) -> Result<PubKey, &'static str> {
+    request[name]
+        .as_str()
+        .and_then(|s| network.from_address(s).ok())
+        .ok_or("Bad account number")
+}
+
+fn hash_field(request: &Value, name: &str) -> Result<Hash, &'static str> {
+    request[name]
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or("Bad hash number")
+}
+
+/// Read an optional count, which clients may send as a string or a number
+fn count_field(request: &Value) -> Result<Option<usize>, &'static str> {
+    match request.get("count") {
+        None => Ok(None),
+        Some(&Value::String(ref s)) => s.parse().map(Some).map_err(|_| "Invalid count limit"),
+        Some(v) => v.as_u64()
+            .map(|c| Some(c as usize))
+            .ok_or("Invalid count limit"),
+    }
+}
+
+/// Read an optional flag, which clients may send as a boolean or a string
+fn flag_field(request: &Value, name: &str) -> bool {
+    match request[name] {
+        Value::Bool(b) => b,
+        Value::String(ref s) => s == "true",
+        _ => false,
+    }
+}
+
+/// Read an optional work threshold as 16 hex digits
+fn difficulty_field(request: &Value, default: u64) -> Result<u64, &'static str> {
+    match request["difficulty"].as_str() {
+        Some(s) if s.len() == 16 => u64::from_str_radix(s, 16).map_err(|_| "Bad difficulty"),
+        Some(_) => Err("Bad difficulty"),
+        None => Ok(default),
+    }
+}
+
+fn account_balance<S: BlockStorage>(
+    storage: &mut S,
+    network: &NetworkParams,
+    request: &Value,
+) -> RpcResult {
+    let account = account_field(network, request, "account")?;
+    let balance = storage
+        .account_info(account)
+        .map(|info| info.balance)
+        .unwrap_or(Balance(0));
+    let pending = storage
+        .find_pending(account)
+        .into_iter()
+        .fold(0u128, |sum, (_, p)| sum.saturating_add(p.amount.0));
+    Ok(json!({
+        "balance": balance.0.to_string(),
+        "pending": pending.to_string(),
+    }))
+}
+
+fn account_info<S: BlockStorage>(
+    storage: &mut S,
+    network: &NetworkParams,
+    request: &Value,
+) -> RpcResult {
+    let account = account_field(network, request, "account")?;
+    let info = storage.account_info(account).ok_or("Account not found")?;
+    Ok(json!({
+        "frontier": info.head.to_string(),
+        "open_block": info.open.to_string(),
+        "representative": network.to_address(info.representative),
+        "balance": info.balance.0.to_string(),
+        "block_count": info.block_count.to_string(),
+        "confirmation_height": storage.find_confirmation_height(account).to_string(),
+    }))
+}
+
+fn account_history<S: BlockStorage>(
+    storage: &mut S,
+    network: &NetworkParams,
+    request: &Value,
+) -> RpcResult {
+    let account = account_field(network, request, "account")?;
+    let count = count_field(request)?.ok_or("Invalid count limit")?;
+    let head = match request.get("head") {
+        Some(_) => Some(hash_field(request, "head")?),
+        None => None,
+    };
+    let direction = if flag_field(request, "reverse") {
+        Direction::Forward
+    } else {
+        Direction::Backward
+    };
+    let (entries, next) = History::new(storage, account, direction, head).page(count);
+    let mut history = Vec::with_capacity(entries.len());
+    for entry in entries {
+        // The other account is the destination of a send or the source of a receive
+        let (kind, other, amount) = match entry.delta {
+            Delta::Debit(amount) => {
+                let destination = storage.find_send(entry.hash).ok().map(|s| s.0);
+                ("send", destination, amount)
+            }
+            Delta::Credit(amount) => {
+                let source = storage.find_received(entry.hash);
+                ("receive", source.and_then(|s| storage.find_key(s)), amount)
+            }
+            Delta::Unchanged => ("change", None, Balance(0)),
+        };
+        let mut item = Map::new();
+        item.insert("type".into(), kind.into());
+        if let Some(other) = other {
+            item.insert("account".into(), network.to_address(other).into());
+        }
+        item.insert("amount".into(), amount.0.to_string().into());
+        item.insert("hash".into(), entry.hash.to_string().into());
+        item.insert("balance".into(), entry.balance.0.to_string().into());
+        history.push(Value::Object(item));
+    }
+    let mut response = Map::new();
+    response.insert("account".into(), network.to_address(account).into());
+    response.insert("history".into(), history.into());
+    if let Some(next) = next {
+        // Nano names the continuation after the direction of travel
+        let name = match direction {
+            Direction::Backward => "previous",
+            Direction::Forward => "next",
+        };
+        response.insert(name.into(), next.to_string().into());
+    }
+    Ok(Value::Object(response))
+}
+
+fn block_info<S: BlockStorage>(
+    storage: &mut S,
+    network: &NetworkParams,
+    request: &Value,
+) -> RpcResult {
+    let hash = hash_field(request, "hash")?;
+    let tx = storage.lookup(hash).ok_or("Block not found")?.clone();
+    let account = storage.find_key(hash).ok_or("Internal error")?;
+    let balance = storage.find_balance(hash).ok_or("Internal error")?;
+    let prev_bal = match tx.previous() {
+        Some(previous) => storage.find_balance(previous).ok_or("Internal error")?,
+        None => Balance(0),
+    };
+    let amount = if balance > prev_bal {
+        balance - prev_bal
+    } else {
+        prev_bal - balance
+    };
+    Ok(json!({
+        "block_account": network.to_address(account),
+        "amount": amount.0.to_string(),
+        "balance": balance.0.to_string(),
+        "height": storage.find_height(hash).unwrap_or(0).to_string(),
+        "confirmed": storage.is_confirmed(hash).to_string(),
+        "contents": block_to_json(network, &tx),
+    }))
+}
+
+fn pending<S: BlockStorage>(
+    storage: &mut S,
+    network: &NetworkParams,
+    request: &Value,
+) -> RpcResult {
+    let account = account_field(network, request, "account")?;
+    let count = count_field(request)?.unwrap_or(usize::max_value());
+    let mut pending = storage.find_pending(account);
+    pending.sort_by_key(|&(hash, _)| hash);
+    pending.truncate(count);
+    let blocks = if flag_field(request, "source") {
+        let mut blocks = Map::new();
+        for (hash, p) in pending {
+            let value = json!({
+                "amount": p.amount.0.to_string(),
+                "source": network.to_address(p.source),
+            });
+            blocks.insert(hash.to_string(), value);
+        }
+        Value::Object(blocks)
+    } else {
+        pending
+            .into_iter()
+            .map(|(hash, _)| Value::from(hash.to_string()))
+            .collect()
+    };
+    Ok(json!({ "blocks": blocks }))
+}
+
+fn process<S: BlockStorage>(
+    storage: &mut S,
+    network: &NetworkParams,
+    request: &Value,
+) -> RpcResult {
+    // The block is usually sent as a string of JSON, but an object is accepted too
+    let block = match request["block"] {
+        Value::String(ref s) => serde_json::from_str(s).map_err(|_| "Block is invalid")?,
+        ref v => v.clone(),
+    };
+    let tx = block_from_json(network, &block).ok_or("Block is invalid")?;
+    let hash = tx.hash();
+    storage.insert(tx).map_err(failure_message)?;
+    Ok(json!({ "hash": hash.to_string() }))
+}
+
+fn failure_message(failure: Failure) -> &'static str {
+    match failure {
+        Failure::Duplicate => "Old block",
+        Failure::Signature => "Bad signature",
+        Failure::Fork => "Fork",
+        Failure::Work => "Block work is less than threshold",
+        Failure::Received => "Unreceivable",
+        Failure::ZeroSend | Failure::OverSend => "Balance mismatch",
+        Failure::Missing => "Gap previous or source block",
+        Failure::Confirmed => "Block is confirmed",
+        Failure::Invalid | Failure::Malformed => "Block is invalid",
+        Failure::Storage => "Storage failure",
+        Failure::Unreachable => "Internal error",
+    }
+}
+
+/// The difficulty reached by some work, and how it compares to the base threshold
+fn work_difficulty(root: Hash, work: Work, difficulty: Difficulty) -> (String, String) {
+    let hash = work_hash(&root.0, work);
+    let value: u64 = hash.into();
+    (format!("{:016X}", value), difficulty.multiplier(hash).to_string())
+}
+
+/// Generate work over a root hash
+///
+/// This uses a `WorkGenerator` rather than `compute_work`, which needs a whole transaction, runs
+/// on one thread and can't be stopped. The search gives up after `WORK_TIMEOUT`, or once `stop` is
+/// set, and thresholds over `MAX_WORK_MULTIPLIER` times the highest one are refused.
+fn work_generate(
+    request: &Value,
+    difficulty: Difficulty,
+    threads: usize,
+    stop: &AtomicBool,
+) -> RpcResult {
+    let hash = hash_field(request, "hash")?;
+    let threshold = difficulty_field(request, difficulty.send)?;
+    let highest = Difficulty {
+        send: difficulty.highest(),
+        receive: difficulty.highest(),
+    };
+    let max = highest
+        .from_multiplier(MAX_WORK_MULTIPLIER)
+        .expect("Unreachable");
+    if threshold > max {
+        return Err("Difficulty above config max");
+    }
+    // Dropping the generator cancels the search
+    let generator = WorkGenerator::start(hash, threshold, threads);
+    let deadline = Instant::now() + WORK_TIMEOUT;
+    let work = loop {
+        if let Some(work) = generator.try_result() {
+            break work;
+        }
+        if stop.load(Ordering::Relaxed) {
+            return Err("Cancelled");
+        }
+        if Instant::now() >= deadline {
+            return Err("Timed out");
+        }
+        thread::sleep(WORK_POLL);
+    };
+    let (reached, multiplier) = work_difficulty(hash, work, difficulty);
+    Ok(json!({
+        "work": work.to_string(),
+        "difficulty": reached,
+        "multiplier": multiplier,
+    }))
+}
+
+fn work_validate(request: &Value, difficulty: Difficulty) -> RpcResult {
+    let hash = hash_field(request, "hash")?;
+    let work: Work = request["work"]
+        .as_str()
+        .and_then(|s| s.parse().ok())
+        .ok_or("Bad work")?;
+    let threshold = difficulty_field(request, difficulty.send)?;
+    let valid = work_hash(&hash.0, work).verify_threshold(threshold);
+    let (reached, multiplier) = work_difficulty(hash, work, difficulty);
+    Ok(json!({
+        "valid": if valid { "1" } else { "0" },
+        "difficulty": reached,
+        "multiplier": multiplier,
+    }))
+}
+
+/// Encode a block the way Nano's RPC does, legacy blocks carry their balance as hex
+pub fn block_to_json(network: &NetworkParams, tx: &Transaction) -> Value {
+    use transaction::Transaction::*;
+    match tx {
+        &Open(ref o) => json!({
+            "type": "open",
+            "source": o.source.to_string(),
+            "representative": network.to_address(o.representative),
+            "account": network.to_address(o.account),
+            "work": o.work.to_string(),
+            "signature": o.signature.to_string(),
+        }),
+        &Send(ref s) => json!({
+            "type": "send",
+            "previous": s.previous.to_string(),
+            "destination": network.to_address(s.destination),
+            "balance": s.balance.to_string(),
+            "work": s.work.to_string(),
+            "signature": s.signature.to_string(),
+        }),
+        &Receive(ref r) => json!({
+            "type": "receive",
+            "previous": r.previous.to_string(),
+            "source": r.source.to_string(),
+            "work": r.work.to_string(),
+            "signature": r.signature.to_string(),
+        }),
+        &Change(ref c) => json!({
+            "type": "change",
+            "previous": c.previous.to_string(),
+            "representative": network.to_address(c.representative),
+            "work": c.work.to_string(),
+            "signature": c.signature.to_string(),
+        }),
+        &State(ref s) => json!({
+            "type": "state",
+            "account": network.to_address(s.account),
+            "previous": s.previous.to_string(),
+            "representative": network.to_address(s.representative),
+            "balance": s.balance.0.to_string(),
+            "link": s.link.to_string(),
+            "link_as_account": network.to_address(PubKey(s.link.0)),
+            "work": s.work.to_string(),
+            "signature": s.signature.to_string(),
+        }),
+    }
+}
+
+/// Decode a block in the format produced by `block_to_json`
+pub fn block_from_json(network: &NetworkParams, block: &Value) -> Option<Transaction> {
+    fn parse<T: FromStr>(block: &Value, name: &str) -> Option<T> {
+        block[name].as_str()?.parse().ok()
+    }
+    let account = |block: &Value, name: &str| network.from_address(block[name].as_str()?).ok();
+    let work: Work = parse(block, "work")?;
+    let signature: Signature = parse(block, "signature")?;
+    let tx = match block["type"].as_str()? {
+        "open" => Transaction::Open(OpenTransaction {
+            account: account(block, "account")?,
+            source: parse(block, "source")?,
+            representative: account(block, "representative")?,
+            work,
+            signature,
+        }),
+        "send" => Transaction::Send(SendTransaction {
+            previous: parse(block, "previous")?,
+            balance: parse(block, "balance")?,
+            destination: account(block, "destination")?,
+            work,
+            signature,
+        }),
+        "receive" => Transaction::Receive(ReceiveTransaction {
+            previous: parse(block, "previous")?,
+            source: parse(block, "source")?,
+            work,
+            signature,
+        }),
+        "change" => Transaction::Change(ChangeTransaction {
+            previous: parse(block, "previous")?,
+            representative: account(block, "representative")?,
+            work,
+            signature,
+        }),
+        "state" => {
+            // The link can be given as a hash or as an account
+            let link = match parse(block, "link") {
+                Some(link) => link,
+                None => Hash(account(block, "link_as_account")?.0),
+            };
+            Transaction::State(StateTransaction {
+                account: account(block, "account")?,
+                previous: parse(block, "previous")?,
+                representative: account(block, "representative")?,
+                balance: Balance(parse(block, "balance")?),
+                link,
+                work,
+                signature,
+            })
+        }
+        _ => return None,
+    };
+    Some(tx)
+}
@@ -0,0 +1,275 @@
+use byteorder::{ByteOrder, BE, LE};
+
+use transaction::{ChangeTransaction, OpenTransaction, ReceiveTransaction, SendTransaction,
+                  StateTransaction, Transaction};
+use types::{Balance, Hash, PubKey, Signature, Work};
+use errors::Failure;
+
+/// The type tag that prefixes a serialized block, as used by the RaiBlocks wire format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Invalid = 0,
+    NotABlock = 1,
+    Send = 2,
+    Receive = 3,
+    Open = 4,
+    Change = 5,
+    State = 6,
+}
+
+impl BlockType {
+    pub fn from_u8(tag: u8) -> Result<BlockType, Failure> {
+        use serialize::BlockType::*;
+        match tag {
+            0 => Ok(Invalid),
+            1 => Ok(NotABlock),
+            2 => Ok(Send),
+            3 => Ok(Receive),
+            4 => Ok(Open),
+            5 => Ok(Change),
+            6 => Ok(State),
+            _ => Err(Failure::Malformed),
+        }
+    }
+    /// The size of a serialized block of this type, not including the type tag
+    pub fn size(&self) -> Option<usize> {
+        use serialize::BlockType::*;
+        match *self {
+            Send => Some(SendTransaction::SIZE),
+            Receive => Some(ReceiveTransaction::SIZE),
+            Open => Some(OpenTransaction::SIZE),
+            Change => Some(ChangeTransaction::SIZE),
+            State => Some(StateTransaction::SIZE),
+            Invalid | NotABlock => None,
+        }
+    }
+}
+
+/// Conversion of a block to and from its wire representation
+///
+/// Fields are written in the same order as `RaiHashImpl::hash_elements`, followed by the
+/// signature and the work. Balances are written big-endian and work little-endian, matching the
+/// RaiBlocks legacy block layout. State blocks are the exception, their work is big-endian.
+pub trait RaiSerialize: Sized {
+    /// The size of the serialized block, not including the type tag
+    const SIZE: usize;
+    fn serialize(&self, out: &mut Vec<u8>);
+    /// Parse a block from the start of `bytes`, any bytes past `SIZE` are ignored
+    fn deserialize(bytes: &[u8]) -> Result<Self, Failure>;
+}
+
+/// Reads fixed size fields from the front of a byte slice
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], Failure> {
+        if self.bytes.len() < len {
+            return Err(Failure::Malformed);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+    pub(crate) fn hash(&mut self) -> Result<Hash, Failure> {
+        let mut hash = Hash::default();
+        hash.0.copy_from_slice(self.take(32)?);
+        Ok(hash)
+    }
+    pub(crate) fn pubkey(&mut self) -> Result<PubKey, Failure> {
+        self.hash().map(|h| PubKey(h.0))
+    }
+    pub(crate) fn balance(&mut self) -> Result<Balance, Failure> {
+        let bytes = self.take(16)?;
+        let high = BE::read_u64(&bytes[..8]) as u128;
+        let low = BE::read_u64(&bytes[8..]) as u128;
+        Ok(Balance(high << 64 | low))
+    }
+    pub(crate) fn signature(&mut self) -> Result<Signature, Failure> {
+        let mut sig = [0; 64];
+        sig.copy_from_slice(self.take(64)?);
+        Ok(Signature(sig))
+    }
+    pub(crate) fn work(&mut self) -> Result<Work, Failure> {
+        Ok(Work(LE::read_u64(self.take(8)?)))
+    }
+    pub(crate) fn work_be(&mut self) -> Result<Work, Failure> {
+        Ok(Work(BE::read_u64(self.take(8)?)))
+    }
+}
+
+pub(crate) fn write_balance(out: &mut Vec<u8>, balance: Balance) {
+    let mut bytes = [0; 16];
+    BE::write_u64(&mut bytes[..8], (balance.0 >> 64) as u64);
+    BE::write_u64(&mut bytes[8..], balance.0 as u64);
+    out.extend_from_slice(&bytes);
+}
+
+pub(crate) fn write_work(out: &mut Vec<u8>, work: Work) {
+    let mut bytes = [0; 8];
+    LE::write_u64(&mut bytes, work.0);
+    out.extend_from_slice(&bytes);
+}
+
+impl RaiSerialize for OpenTransaction {
+    const SIZE: usize = 32 + 32 + 32 + 64 + 8;
+    fn serialize(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.source.0);
+        out.extend_from_slice(self.representative.as_ref());
+        out.extend_from_slice(self.account.as_ref());
+        out.extend_from_slice(&self.signature.0);
+        write_work(out, self.work);
+    }
+    fn deserialize(bytes: &[u8]) -> Result<Self, Failure> {
+        let mut r = Reader::new(bytes);
+        Ok(Self {
+            source: r.hash()?,
+            representative: r.pubkey()?,
+            account: r.pubkey()?,
+            signature: r.signature()?,
+            work: r.work()?,
+        })
+    }
+}
+
+impl RaiSerialize for SendTransaction {
+    const SIZE: usize = 32 + 32 + 16 + 64 + 8;
+    fn serialize(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.previous.0);
+        out.extend_from_slice(self.destination.as_ref());
+        write_balance(out, self.balance);
+        out.extend_from_slice(&self.signature.0);
+        write_work(out, self.work);
+    }
+    fn deserialize(bytes: &[u8]) -> Result<Self, Failure> {
+        let mut r = Reader::new(bytes);
+        Ok(Self {
+            previous: r.hash()?,
+            destination: r.pubkey()?,
+            balance: r.balance()?,
+            signature: r.signature()?,
+            work: r.work()?,
+        })
+    }
+}
+
+impl RaiSerialize for ReceiveTransaction {
+    const SIZE: usize = 32 + 32 + 64 + 8;
+    fn serialize(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.previous.0);
+        out.extend_from_slice(&self.source.0);
+        out.extend_from_slice(&self.signature.0);
+        write_work(out, self.work);
+    }
+    fn deserialize(bytes: &[u8]) -> Result<Self, Failure> {
+        let mut r = Reader::new(bytes);
+        Ok(Self {
+            previous: r.hash()?,
+            source: r.hash()?,
+            signature: r.signature()?,
+            work: r.work()?,
+        })
+    }
+}
+
+impl RaiSerialize for ChangeTransaction {
+    const SIZE: usize = 32 + 32 + 64 + 8;
+    fn serialize(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.previous.0);
+        out.extend_from_slice(self.representative.as_ref());
+        out.extend_from_slice(&self.signature.0);
+        write_work(out, self.work);
+    }
+    fn deserialize(bytes: &[u8]) -> Result<Self, Failure> {
+        let mut r = Reader::new(bytes);
+        Ok(Self {
+            previous: r.hash()?,
+            representative: r.pubkey()?,
+            signature: r.signature()?,
+            work: r.work()?,
+        })
+    }
+}
+
+impl RaiSerialize for StateTransaction {
+    const SIZE: usize = 32 + 32 + 32 + 16 + 32 + 64 + 8;
+    fn serialize(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.account.as_ref());
+        out.extend_from_slice(&self.previous.0);
+        out.extend_from_slice(self.representative.as_ref());
+        write_balance(out, self.balance);
+        out.extend_from_slice(&self.link.0);
+        out.extend_from_slice(&self.signature.0);
+        let mut work = [0; 8];
+        BE::write_u64(&mut work, self.work.0);
+        out.extend_from_slice(&work);
+    }
+    fn deserialize(bytes: &[u8]) -> Result<Self, Failure> {
+        let mut r = Reader::new(bytes);
+        Ok(Self {
+            account: r.pubkey()?,
+            previous: r.hash()?,
+            representative: r.pubkey()?,
+            balance: r.balance()?,
+            link: r.hash()?,
+            signature: r.signature()?,
+            work: r.work_be()?,
+        })
+    }
+}
+
+impl Transaction {
+    pub fn block_type(&self) -> BlockType {
+        use transaction::Transaction::*;
+        match self {
+            &Open(_) => BlockType::Open,
+            &Send(_) => BlockType::Send,
+            &Receive(_) => BlockType::Receive,
+            &Change(_) => BlockType::Change,
+            &State(_) => BlockType::State,
+        }
+    }
+    /// Serialize this transaction without the leading type tag
+    pub fn serialize(&self, out: &mut Vec<u8>) {
+        use transaction::Transaction::*;
+        match self {
+            &Open(ref o) => o.serialize(out),
+            &Send(ref s) => s.serialize(out),
+            &Receive(ref r) => r.serialize(out),
+            &Change(ref c) => c.serialize(out),
+            &State(ref s) => s.serialize(out),
+        }
+    }
+    /// Parse a transaction of the given type from the start of `bytes`
+    pub fn deserialize(ty: BlockType, bytes: &[u8]) -> Result<Transaction, Failure> {
+        match ty {
+            BlockType::Open => OpenTransaction::deserialize(bytes).map(Into::into),
+            BlockType::Send => SendTransaction::deserialize(bytes).map(Into::into),
+            BlockType::Receive => ReceiveTransaction::deserialize(bytes).map(Into::into),
+            BlockType::Change => ChangeTransaction::deserialize(bytes).map(Into::into),
+            BlockType::State => StateTransaction::deserialize(bytes).map(Into::into),
+            BlockType::Invalid | BlockType::NotABlock => Err(Failure::Malformed),
+        }
+    }
+    /// Serialize this transaction, prefixed with its type tag
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let ty = self.block_type();
+        let mut out = Vec::with_capacity(1 + ty.size().unwrap_or(0));
+        out.push(ty as u8);
+        self.serialize(&mut out);
+        out
+    }
+    /// Parse a transaction prefixed with its type tag, `bytes` must contain exactly one block
+    pub fn from_bytes(bytes: &[u8]) -> Result<Transaction, Failure> {
+        let (&tag, body) = bytes.split_first().ok_or(Failure::Malformed)?;
+        let ty = BlockType::from_u8(tag)?;
+        if ty.size() != Some(body.len()) {
+            return Err(Failure::Malformed);
+        }
+        Transaction::deserialize(ty, body)
+    }
+}
@@ -1,7 +1,10 @@
 use blockstorage::{BlockStorage, Storage};
-use transaction::{OpenTransaction, RaiHash, RaiWork, SendTransaction, Transaction};
+use transaction::{OpenTransaction, RaiHash, RaiWork, ReceiveTransaction, SendTransaction, StateKind,
+                  StateTransaction, Transaction};
 use genesis::{BALANCE, TEST_BLOCK, TEST_PRIVATE_KEY};
-use types::{Balance, Work};
+use types::{Balance, Hash, PubKey, Work};
+use errors::Failure;
+use work::{compute_work, Difficulty};
 
 use ed25519_dalek::{Keypair, PublicKey, SecretKey};
 use blake2::Blake2b;
@@ -9,6 +12,44 @@ use rand::{thread_rng, SeedableRng, XorShiftRng};
 
 const TEST_SEED: [u32; 4] = [3435123151, 546876541, 146548468, 894165236];
 
+/// The key of the test genesis account
+fn test_keypair() -> Keypair {
+    let secret = SecretKey::from_bytes(&TEST_PRIVATE_KEY).unwrap();
+    let public = PublicKey::from_secret::<Blake2b>(&secret);
+    Keypair { secret, public }
+}
+
+/// A key pair for a second account, the same every time
+fn test_dest() -> Keypair {
+    Keypair::generate::<Blake2b>(&mut XorShiftRng::from_seed(TEST_SEED))
+}
+
+/// A send with work for the test network
+fn test_send(
+    keypair: &Keypair,
+    previous: Hash,
+    balance: Balance,
+    destination: PubKey,
+) -> SendTransaction {
+    let mut send = SendTransaction::new_without_work(keypair, previous, balance, destination);
+    send.work = compute_work(&send, Difficulty::TEST.send);
+    send
+}
+
+/// An open of `keypair`'s account with work for the test network, representing itself
+fn test_open(keypair: &Keypair, source: Hash) -> OpenTransaction {
+    let mut open = OpenTransaction::new_without_work(keypair, source, None);
+    open.work = compute_work(&open, Difficulty::TEST.receive);
+    open
+}
+
+/// A receive with work for the test network
+fn test_receive(keypair: &Keypair, previous: Hash, source: Hash) -> ReceiveTransaction {
+    let mut receive = ReceiveTransaction::new_without_work(keypair, previous, source);
+    receive.work = compute_work(&receive, Difficulty::TEST.receive);
+    receive
+}
+
 #[test]
 fn test_storage() {
     let mut s = Storage::new_test();
@@ -35,3 +76,955 @@ fn test_storage() {
     s.insert(open.into()).unwrap();
     println!("{:#?}", s);
 }
+
+#[test]
+fn test_serialize() {
+    use serialize::BlockType;
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let mut send = SendTransaction::new_without_work(
+        &keypair,
+        TEST_BLOCK.hash(),
+        BALANCE - Balance(1),
+        dest.public.into(),
+    );
+    send.work = Work(11670401854380690467);
+    let send: Transaction = send.into();
+    let open: Transaction = OpenTransaction::new_without_work(&dest, send.hash(), None).into();
+    for tx in &[send, open] {
+        let bytes = tx.to_bytes();
+        assert_eq!(Some(bytes.len() - 1), tx.block_type().size());
+        let parsed = Transaction::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed.hash(), tx.hash());
+        assert_eq!(parsed.to_bytes(), bytes);
+        assert_eq!(
+            Transaction::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            Failure::Malformed
+        );
+    }
+    let genesis: Transaction = TEST_BLOCK.into();
+    let mut bytes = genesis.to_bytes();
+    assert_eq!(bytes[0], BlockType::Open as u8);
+    bytes[0] = 0xff;
+    assert_eq!(Transaction::from_bytes(&bytes).unwrap_err(), Failure::Malformed);
+}
+
+#[test]
+fn test_state_blocks() {
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let mut send = StateTransaction::new_without_work(
+        &keypair,
+        TEST_BLOCK.hash(),
+        TEST_BLOCK.representative,
+        BALANCE - Balance(10),
+        Hash(dest.public.to_bytes()),
+    );
+    send.work = Work(11670401854380690467);
+    assert_eq!(send.kind(&mut s), Ok(StateKind::Send));
+    let send_hash = send.hash();
+
+    // Claiming more than was sent is rejected
+    let mut open = StateTransaction::new_without_work(
+        &dest,
+        Default::default(),
+        dest.public.into(),
+        Balance(11),
+        send_hash,
+    );
+    open.work = Work(4421055909967421080);
+    s.insert(send.into()).unwrap();
+    assert!(s.is_unspent(send_hash));
+    assert_eq!(s.insert(open.into()), Err(Failure::Invalid));
+
+    let mut open = StateTransaction::new_without_work(
+        &dest,
+        Default::default(),
+        dest.public.into(),
+        Balance(10),
+        send_hash,
+    );
+    open.work = Work(4421055909967421080);
+    let open_hash = open.hash();
+    s.insert(open.into()).unwrap();
+    assert!(!s.is_unspent(send_hash));
+    assert_eq!(s.find_balance(open_hash), Some(Balance(10)));
+    assert_eq!(s.find_key(open_hash), Some(dest.public.into()));
+    assert_eq!(s.find_head(dest.public.into()), Some(open_hash));
+}
+
+#[test]
+fn test_disk_storage() {
+    use std::fs::{metadata, remove_file, File, OpenOptions};
+    use std::io::{ErrorKind, Read, Write};
+    use std::env::temp_dir;
+    use std::process;
+    use diskstorage::DiskStorage;
+
+    let path = temp_dir().join(format!("cryptocurrency-test-disk-{}.log", process::id()));
+    let _ = remove_file(&path);
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let mut send = SendTransaction::new_without_work(
+        &keypair,
+        TEST_BLOCK.hash(),
+        BALANCE - Balance(1),
+        dest.public.into(),
+    );
+    send.work = Work(11670401854380690467);
+    let send_hash = send.hash();
+    let mut open = OpenTransaction::new_without_work(&dest, send_hash, None);
+    open.work = Work(4421055909967421080);
+    let open_hash = open.hash();
+    {
+        let mut s = DiskStorage::open_with(&path, Storage::new_test()).unwrap();
+        s.insert(send.into()).unwrap();
+        assert!(s.is_unspent(send_hash));
+        let mut contender = SendTransaction::new_without_work(
+            &keypair,
+            TEST_BLOCK.hash(),
+            BALANCE - Balance(2),
+            dest.public.into(),
+        );
+        contender.work = compute_work(&contender, Difficulty::TEST.send);
+        assert_eq!(s.insert(contender.clone().into()), Err(Failure::Fork));
+        // Contenders are only logged the first time they are seen
+        let len = metadata(&path).unwrap().len();
+        assert_eq!(s.insert(contender.into()), Err(Failure::Fork));
+        assert_eq!(metadata(&path).unwrap().len(), len);
+    }
+    {
+        let mut s = DiskStorage::open_with(&path, Storage::new_test()).unwrap();
+        assert_eq!(s.find_head(TEST_BLOCK.account), Some(send_hash));
+        s.insert(open.into()).unwrap();
+    }
+    // Simulate a crash part way through writing a record
+    OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(&[0, 0xff, 0, 0])
+        .unwrap();
+    {
+        let mut s = DiskStorage::open_with(&path, Storage::new_test()).unwrap();
+        assert_eq!(s.find_head(dest.public.into()), Some(open_hash));
+        assert_eq!(s.find_balance(open_hash), Some(Balance(1)));
+        assert!(!s.is_unspent(send_hash));
+        s.rollback(open_hash).unwrap();
+    }
+    {
+        let mut s = DiskStorage::open_with(&path, Storage::new_test()).unwrap();
+        assert_eq!(s.find_head(dest.public.into()), None);
+        assert!(s.is_unspent(send_hash));
+        s.cement(send_hash).unwrap();
+    }
+    {
+        let mut s = DiskStorage::open_with(&path, Storage::new_test()).unwrap();
+        assert!(s.is_confirmed(send_hash));
+    }
+    // Corruption before the last record isn't a torn write, so the log must be left alone
+    let mut bytes = Vec::new();
+    File::open(&path).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes[10] ^= 0xff;
+    File::create(&path).unwrap().write_all(&bytes).unwrap();
+    let err = DiskStorage::open_with(&path, Storage::new_test()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(metadata(&path).unwrap().len(), bytes.len() as u64);
+    remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_custom_network() {
+    use errors::AddressError;
+    use genesis::BETA_BLOCK;
+    use network::NetworkParams;
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let genesis_key = Keypair::generate::<Blake2b>(&mut rng);
+    let dest = Keypair::generate::<Blake2b>(&mut rng);
+    let mut params = NetworkParams::custom(&genesis_key, Balance(1000), Difficulty::TEST);
+    params.address_prefix = "tst_";
+    let genesis_hash = params.genesis.hash();
+    let genesis_account: PubKey = genesis_key.public.into();
+    let address = params.to_address(genesis_account);
+    assert!(address.starts_with("tst_"));
+    assert_eq!(params.from_address(&address), Ok(genesis_account));
+    let live_address = genesis_account.to_address();
+    assert_eq!(params.from_address(&live_address), Err(AddressError::Prefix));
+    let live = NetworkParams::live();
+    assert_eq!(live.from_address(&live_address), Ok(genesis_account));
+    let nano_address = genesis_account.to_address_with_prefix("nano_");
+    assert_eq!(live.from_address(&nano_address), Ok(genesis_account));
+    // The beta network has its own genesis and magic, but the live thresholds
+    let beta = NetworkParams::beta();
+    assert_ne!(beta.magic, live.magic);
+    assert_eq!(beta.difficulty, live.difficulty);
+    let beta_account = beta.genesis.account;
+    let mut beta_storage = Storage::with_network(beta);
+    assert_eq!(beta_storage.find_head(beta_account), Some(BETA_BLOCK.hash()));
+    let mut s = Storage::with_network(params);
+    assert_eq!(s.find_head(genesis_key.public.into()), Some(genesis_hash));
+
+    let send = test_send(&genesis_key, genesis_hash, Balance(600), dest.public.into());
+    let open = test_open(&dest, send.hash());
+    let open_hash = open.hash();
+    s.insert(send.into()).unwrap();
+    s.insert(open.into()).unwrap();
+    assert_eq!(s.find_balance(open_hash), Some(Balance(400)));
+}
+
+#[test]
+fn test_wallet() {
+    use wallet::Wallet;
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let wallet = Wallet::generate(&mut XorShiftRng::from_seed(TEST_SEED));
+    let send = test_send(&keypair, TEST_BLOCK.hash(), BALANCE - Balance(5), wallet.account(3));
+    let mut open = wallet.open(3, send.hash(), None);
+    open.work = compute_work(&open, Difficulty::TEST.receive);
+    let mut back = wallet.send(3, open.hash(), Balance(2), TEST_BLOCK.account);
+    back.work = compute_work(&back, Difficulty::TEST.send);
+    let back_hash = back.hash();
+    s.insert(send.into()).unwrap();
+    s.insert(open.into()).unwrap();
+    s.insert(back.into()).unwrap();
+    assert_eq!(s.find_head(wallet.account(3)), Some(back_hash));
+    assert!(s.is_unspent(back_hash));
+}
+
+#[test]
+fn test_pending() {
+    use blockstorage::Pending;
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let mut previous = TEST_BLOCK.hash();
+    let mut sends = Vec::new();
+    for &balance in &[BALANCE - Balance(3), BALANCE - Balance(10)] {
+        let send = test_send(&keypair, previous, balance, dest.public.into());
+        previous = send.hash();
+        sends.push(previous);
+        s.insert(send.into()).unwrap();
+    }
+    let mut pending = s.find_pending(dest.public.into());
+    pending.sort_by_key(|&(_, p)| p.amount);
+    let source = TEST_BLOCK.account;
+    assert_eq!(
+        pending,
+        vec![
+            (sends[0], Pending { source, amount: Balance(3) }),
+            (sends[1], Pending { source, amount: Balance(7) }),
+        ]
+    );
+
+    let open = test_open(&dest, sends[1]);
+    s.insert(open.into()).unwrap();
+    assert_eq!(
+        s.find_pending(dest.public.into()),
+        vec![(sends[0], Pending { source, amount: Balance(3) })]
+    );
+    assert!(s.find_pending(TEST_BLOCK.account).is_empty());
+}
+
+#[test]
+fn test_weights() {
+    use transaction::ChangeTransaction;
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let mut rng = XorShiftRng::from_seed(TEST_SEED);
+    let dest = Keypair::generate::<Blake2b>(&mut rng);
+    let rep: PubKey = Keypair::generate::<Blake2b>(&mut rng).public.into();
+    let genesis = TEST_BLOCK.account;
+    assert_eq!(s.find_weight(genesis), BALANCE);
+
+    let send = test_send(&keypair, TEST_BLOCK.hash(), Balance(100), dest.public.into());
+    let mut open = OpenTransaction::new_without_work(&dest, send.hash(), Some(rep));
+    open.work = compute_work(&open, Difficulty::TEST.receive);
+    let mut change = ChangeTransaction::new_without_work(&dest, open.hash(), dest.public.into());
+    change.work = compute_work(&change, Difficulty::TEST.send);
+    s.insert(send.into()).unwrap();
+    // Pending sends don't count towards anyone's weight
+    assert_eq!(s.find_weight(genesis), Balance(100));
+    s.insert(open.into()).unwrap();
+    assert_eq!(s.find_representative(dest.public.into()), Some(rep));
+    assert_eq!(s.find_weight(rep), BALANCE - Balance(100));
+    s.insert(change.into()).unwrap();
+    assert_eq!(s.find_weight(rep), Balance(0));
+    assert_eq!(s.find_weight(dest.public.into()), BALANCE - Balance(100));
+    assert_eq!(s.find_representative(genesis), Some(genesis));
+}
+
+#[test]
+fn test_forks() {
+    use blockstorage::MAX_CONTENDERS;
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let genesis = TEST_BLOCK.account;
+    let root = TEST_BLOCK.hash();
+
+    let send = test_send(&keypair, root, Balance(100), dest.public.into());
+    let open = test_open(&dest, send.hash());
+    let contender = test_send(&keypair, root, Balance(1), genesis);
+    s.insert(send.clone().into()).unwrap();
+    s.insert(open.clone().into()).unwrap();
+    assert!(s.find_forks().is_empty());
+    assert_eq!(s.insert(contender.clone().into()), Err(Failure::Fork));
+    assert_eq!(s.find_forks(), vec![root]);
+    let conflicts: Vec<Hash> = s.find_conflicts(root).iter().map(|t| t.hash()).collect();
+    assert_eq!(conflicts, vec![send.hash(), contender.hash()]);
+
+    // Replacing the send also removes the open that received it
+    s.resolve_fork(root, contender.hash()).unwrap();
+    assert!(s.find_forks().is_empty());
+    assert_eq!(s.find_head(genesis), Some(contender.hash()));
+    assert_eq!(s.find_head(dest.public.into()), None);
+    assert!(s.lookup(open.hash()).is_none());
+    assert!(s.is_unspent(contender.hash()));
+    assert!(!s.is_unspent(send.hash()));
+    assert_eq!(s.find_weight(genesis), Balance(1));
+    assert_eq!(s.find_pending(genesis)[0].1.amount, BALANCE - Balance(1));
+    // Once resolved, the loser is a fork again
+    assert_eq!(s.insert(send.into()), Err(Failure::Fork));
+    assert_eq!(s.find_forks(), vec![root]);
+
+    // A contender is only checked for its signature and work, so it can fail to replace the
+    // incumbent, which must then be restored
+    let next = test_send(&keypair, contender.hash(), Balance(0), genesis);
+    let invalid = test_send(&keypair, contender.hash(), Balance(2), genesis);
+    s.insert(next.clone().into()).unwrap();
+    assert_eq!(s.insert(invalid.clone().into()), Err(Failure::Fork));
+    assert_eq!(
+        s.resolve_fork(contender.hash(), invalid.hash()),
+        Err(Failure::OverSend)
+    );
+    assert_eq!(s.find_head(genesis), Some(next.hash()));
+    assert_eq!(s.find_weight(genesis), Balance(0));
+    assert_eq!(s.find_conflicts(contender.hash()).len(), 2);
+
+    // Only a limited number of contenders are kept for each root
+    for i in 0..MAX_CONTENDERS as u8 {
+        let destination = PubKey([i; 32]);
+        let spam = test_send(&keypair, contender.hash(), Balance(0), destination);
+        assert_eq!(s.insert(spam.into()), Err(Failure::Fork));
+    }
+    assert_eq!(s.find_conflicts(contender.hash()).len(), 1 + MAX_CONTENDERS);
+}
+
+#[test]
+fn test_rollback() {
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let genesis = TEST_BLOCK.account;
+
+    let send = test_send(&keypair, TEST_BLOCK.hash(), Balance(100), dest.public.into());
+    let open = test_open(&dest, send.hash());
+    let next = test_send(&keypair, send.hash(), Balance(50), dest.public.into());
+    s.insert(send.clone().into()).unwrap();
+    s.insert(open.clone().into()).unwrap();
+    s.insert(next.clone().into()).unwrap();
+
+    // Rolling back the open only touches the destination's chain
+    let removed: Vec<Hash> = s.rollback(open.hash()).unwrap().iter().map(|t| t.hash()).collect();
+    assert_eq!(removed, vec![open.hash()]);
+    assert_eq!(s.find_head(dest.public.into()), None);
+    assert!(s.is_unspent(send.hash()));
+    assert_eq!(s.find_pending(dest.public.into()).len(), 2);
+    assert_eq!(s.find_weight(dest.public.into()), Balance(0));
+    s.insert(open.clone().into()).unwrap();
+
+    // Rolling back the first send removes the later send and the open that received it
+    let removed: Vec<Hash> = s.rollback(send.hash()).unwrap().iter().map(|t| t.hash()).collect();
+    assert_eq!(removed, vec![next.hash(), open.hash(), send.hash()]);
+    assert_eq!(s.find_head(genesis), Some(TEST_BLOCK.hash()));
+    assert_eq!(s.find_successor(TEST_BLOCK.hash()), None);
+    assert_eq!(s.find_head(dest.public.into()), None);
+    assert!(s.lookup(send.hash()).is_none());
+    assert!(s.find_pending(dest.public.into()).is_empty());
+    assert_eq!(s.find_weight(genesis), BALANCE);
+    assert_eq!(s.find_weight(dest.public.into()), Balance(0));
+    assert_eq!(s.rollback(send.hash()).unwrap_err(), Failure::Missing);
+}
+
+#[test]
+fn test_votes() {
+    use vote::{Tally, Vote};
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let other = test_dest();
+    let root = TEST_BLOCK.hash();
+
+    let send = test_send(&keypair, root, Balance(1), other.public.into());
+    let contender = test_send(&keypair, root, Balance(2), other.public.into());
+
+    let mut vote = Vote::new(&other, 1, vec![send.hash()]);
+    assert!(vote.verify().is_ok());
+    vote.sequence = 2;
+    assert_eq!(vote.verify(), Err(Failure::Signature));
+
+    s.insert(send.clone().into()).unwrap();
+    assert_eq!(s.insert(contender.clone().into()), Err(Failure::Fork));
+    let mut tally = Tally::new(root);
+    // Ties go to the incumbent
+    assert_eq!(tally.winner(&mut s), Some((send.hash(), Balance(0))));
+    let mut tied = Tally::new(root);
+    tied.add(&mut s, Vote::new(&other, 1, vec![contender.hash()])).unwrap();
+    assert_eq!(tied.winner(&mut s), Some((send.hash(), Balance(0))));
+    tally.add(&mut s, Vote::new(&other, 1, vec![send.hash()])).unwrap();
+    assert_eq!(tally.add(&mut s, vote), Err(Failure::Signature));
+    assert_eq!(
+        tally
+            .add(&mut s, Vote::new(&other, 1, vec![contender.hash()]))
+            .unwrap_err(),
+        Failure::Duplicate
+    );
+    tally.add(&mut s, Vote::new(&keypair, 5, vec![contender.hash()])).unwrap();
+    // The genesis representative holds what is left after the send, the other has no weight
+    let weight = s.find_weight(TEST_BLOCK.representative);
+    assert_eq!(weight, Balance(1));
+    assert_eq!(tally.weight(&mut s, send.hash()), Balance(0));
+    assert_eq!(tally.winner(&mut s), Some((contender.hash(), weight)));
+    // A later vote replaces the earlier one
+    tally.add(&mut s, Vote::new(&keypair, 6, vec![send.hash()])).unwrap();
+    assert_eq!(tally.winner(&mut s), Some((send.hash(), weight)));
+    assert_eq!(tally.find_vote(keypair.public.into()).unwrap().sequence, 6);
+    // Voting in another election doesn't take weight away from this one
+    let elsewhere = Vote::new(&keypair, 7, vec![Hash([1; 32])]);
+    assert_eq!(tally.add(&mut s, elsewhere), Err(Failure::Invalid));
+    assert_eq!(tally.winner(&mut s), Some((send.hash(), weight)));
+}
+
+#[test]
+fn test_cement() {
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let root = TEST_BLOCK.hash();
+
+    let send = test_send(&keypair, root, Balance(100), dest.public.into());
+    let open = test_open(&dest, send.hash());
+    let next = test_send(&keypair, send.hash(), Balance(50), dest.public.into());
+    let contender = test_send(&keypair, root, Balance(1), dest.public.into());
+    s.insert(send.clone().into()).unwrap();
+    s.insert(open.clone().into()).unwrap();
+    s.insert(next.clone().into()).unwrap();
+    assert_eq!(s.insert(contender.clone().into()), Err(Failure::Fork));
+    assert!(s.is_confirmed(root));
+    assert!(!s.is_confirmed(send.hash()));
+    assert_eq!(s.find_height(next.hash()), Some(3));
+
+    // Cementing the open also cements the send it received
+    s.cement(open.hash()).unwrap();
+    assert!(s.is_confirmed(open.hash()));
+    assert!(s.is_confirmed(send.hash()));
+    assert!(!s.is_confirmed(next.hash()));
+    assert_eq!(s.find_confirmation_height(TEST_BLOCK.account), 2);
+    assert_eq!(s.rollback(send.hash()).unwrap_err(), Failure::Confirmed);
+    assert_eq!(s.rollback(open.hash()).unwrap_err(), Failure::Confirmed);
+    assert_eq!(s.resolve_fork(root, contender.hash()), Err(Failure::Confirmed));
+    assert_eq!(s.find_head(TEST_BLOCK.account), Some(next.hash()));
+    // Unconfirmed history can still be undone
+    s.rollback(next.hash()).unwrap();
+    s.resolve_fork(root, send.hash()).unwrap();
+    assert!(s.find_forks().is_empty());
+}
+
+#[test]
+fn test_verify_parallel() {
+    use transaction::{verify_parallel, ReceiveTransaction};
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+
+    let send = SendTransaction::new_without_work(
+        &keypair,
+        TEST_BLOCK.hash(),
+        Balance(100),
+        dest.public.into(),
+    );
+    let open = OpenTransaction::new_without_work(&dest, send.hash(), None);
+    // The signer of the receive is only known from the open in the slice
+    let receive = ReceiveTransaction::new_without_work(&dest, open.hash(), send.hash());
+    // Signed by the wrong account
+    let forged =
+        SendTransaction::new_without_work(&dest, send.hash(), Balance(0), dest.public.into());
+    // Follows a block nobody knows about
+    let orphan = ReceiveTransaction::new_without_work(&dest, Hash([1; 32]), send.hash());
+    let mut tampered = send.clone();
+    tampered.balance = Balance(0);
+    let txs: Vec<Transaction> = vec![
+        send.into(),
+        forged.into(),
+        open.into(),
+        receive.into(),
+        orphan.into(),
+        tampered.into(),
+    ];
+    assert_eq!(
+        verify_parallel(&mut s, &txs, 4),
+        vec![
+            (1, Failure::Signature),
+            (4, Failure::Missing),
+            (5, Failure::Signature),
+        ]
+    );
+    // Chains can be given newest first
+    let chain = vec![txs[3].clone(), txs[2].clone()];
+    assert!(verify_parallel(&mut s, &chain, 1).is_empty());
+    assert!(verify_parallel(&mut s, &[], 4).is_empty());
+}
+
+#[test]
+fn test_import() {
+    use import::Importer;
+    let keypair = test_keypair();
+    let dest = test_dest();
+
+    let send = test_send(&keypair, TEST_BLOCK.hash(), Balance(100), dest.public.into());
+    let second = test_send(&keypair, send.hash(), Balance(50), dest.public.into());
+    let open = test_open(&dest, send.hash());
+    let receive = test_receive(&dest, open.hash(), second.hash());
+    let mut lazy =
+        SendTransaction::new_without_work(&keypair, second.hash(), Balance(0), dest.public.into());
+    lazy.work = Work(0);
+
+    let mut importer = Importer::new(Storage::new_test(), 4);
+    // The destination's chain arrives before the sends it depends on
+    let summary = importer.import(vec![receive.clone().into(), open.clone().into()]);
+    assert_eq!(summary.inserted, 0);
+    assert_eq!(summary.unchecked, 2);
+    // The receive waits for the open, which waits for the send
+    let mut missing = importer.find_missing();
+    missing.sort();
+    let mut expected = vec![send.hash(), open.hash()];
+    expected.sort();
+    assert_eq!(missing, expected);
+
+    let summary = importer.import(vec![
+        lazy.clone().into(),
+        second.clone().into(),
+        send.clone().into(),
+    ]);
+    assert_eq!(summary.inserted, 4);
+    assert_eq!(summary.unchecked, 0);
+    assert_eq!(summary.rejected, vec![(lazy.hash(), Failure::Work)]);
+    let mut s = importer.into_storage();
+    assert_eq!(s.find_head(dest.public.into()), Some(receive.hash()));
+    assert_eq!(s.find_balance(receive.hash()), Some(BALANCE - Balance(50)));
+
+    // Once the unchecked table is full the oldest transaction is dropped
+    let mut importer = Importer::with_max_unchecked(Storage::new_test(), 2, 1);
+    let summary = importer.import(vec![receive.clone().into(), open.clone().into()]);
+    assert_eq!(summary.unchecked, 1);
+    assert_eq!(importer.find_missing(), vec![send.hash()]);
+    // A parked transaction's signature is checked once its signer arrives
+    let mut forged = second.clone();
+    forged.signature.0[0] ^= 1;
+    let mut importer = Importer::new(Storage::new_test(), 2);
+    let summary = importer.import(vec![forged.clone().into(), open.into()]);
+    assert_eq!(summary.unchecked, 2);
+    let summary = importer.import(vec![send.into()]);
+    assert_eq!(summary.inserted, 2);
+    assert_eq!(summary.unchecked, 0);
+    assert_eq!(summary.rejected, vec![(forged.hash(), Failure::Signature)]);
+}
+
+#[test]
+fn test_account_info() {
+    use blockstorage::AccountInfo;
+    use transaction::ChangeTransaction;
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let genesis = TEST_BLOCK.account;
+
+    let send = test_send(&keypair, TEST_BLOCK.hash(), Balance(100), dest.public.into());
+    let open = test_open(&dest, send.hash());
+    let mut change = ChangeTransaction::new_without_work(&dest, open.hash(), genesis);
+    change.work = compute_work(&change, Difficulty::TEST.send);
+    s.insert(send.clone().into()).unwrap();
+    s.insert(open.clone().into()).unwrap();
+    s.insert(change.clone().into()).unwrap();
+
+    assert_eq!(
+        s.account_info(genesis),
+        Some(AccountInfo {
+            open: TEST_BLOCK.hash(),
+            head: send.hash(),
+            representative: genesis,
+            balance: Balance(100),
+            block_count: 2,
+            modified: 1,
+        })
+    );
+    assert_eq!(
+        s.account_info(dest.public.into()),
+        Some(AccountInfo {
+            open: open.hash(),
+            head: change.hash(),
+            representative: genesis,
+            balance: BALANCE - Balance(100),
+            block_count: 2,
+            modified: 3,
+        })
+    );
+    s.rollback(change.hash()).unwrap();
+    let info = s.account_info(dest.public.into()).unwrap();
+    assert_eq!(info.head, open.hash());
+    assert_eq!(info.representative, dest.public.into());
+    assert_eq!(info.block_count, 1);
+    assert_eq!(info.modified, 4);
+    s.rollback(open.hash()).unwrap();
+    assert_eq!(s.account_info(dest.public.into()), None);
+}
+
+#[test]
+fn test_history() {
+    use history::{Delta, Direction, History};
+    let mut s = Storage::new_test();
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let genesis = TEST_BLOCK.account;
+
+    let send = test_send(&keypair, TEST_BLOCK.hash(), Balance(100), dest.public.into());
+    let second = test_send(&keypair, send.hash(), Balance(50), dest.public.into());
+    let open = test_open(&dest, second.hash());
+    let receive = test_receive(&dest, open.hash(), send.hash());
+    s.insert(send.clone().into()).unwrap();
+    s.insert(second.clone().into()).unwrap();
+    s.insert(open.clone().into()).unwrap();
+    s.insert(receive.clone().into()).unwrap();
+    assert_eq!(s.find_successor(send.hash()), Some(second.hash()));
+    assert_eq!(s.find_successor(second.hash()), None);
+
+    let entries: Vec<(Hash, Balance, Delta)> =
+        History::new(&mut s, genesis, Direction::Backward, None)
+            .map(|e| (e.hash, e.balance, e.delta))
+            .collect();
+    assert_eq!(
+        entries,
+        vec![
+            (second.hash(), Balance(50), Delta::Debit(Balance(50))),
+            (send.hash(), Balance(100), Delta::Debit(BALANCE - Balance(100))),
+            (TEST_BLOCK.hash(), BALANCE, Delta::Credit(BALANCE)),
+        ]
+    );
+
+    // Page forward through the destination's chain one transaction at a time
+    let account = dest.public.into();
+    let (page, next) = History::new(&mut s, account, Direction::Forward, None).page(1);
+    assert_eq!(page[0].hash, open.hash());
+    assert_eq!(page[0].delta, Delta::Credit(Balance(50)));
+    assert_eq!(next, Some(receive.hash()));
+    let (page, next) = History::new(&mut s, account, Direction::Forward, next).page(1);
+    assert_eq!(page[0].balance, BALANCE - Balance(50));
+    assert_eq!(next, None);
+    let start = Some(open.hash());
+    let (page, next) = History::new(&mut s, account, Direction::Backward, start).page(5);
+    assert_eq!(page.len(), 1);
+    assert_eq!(next, None);
+    // A start hash from another account gives nothing
+    assert_eq!(History::new(&mut s, account, Direction::Forward, Some(send.hash())).count(), 0);
+}
+
+#[test]
+fn test_bootstrap() {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::{Arc, Mutex};
+    use bootstrap::{BootstrapClient, BootstrapServer};
+    use protocol::{FrontierReq, Message};
+    use import::Importer;
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let genesis = TEST_BLOCK.account;
+    let magic = Storage::new_test().network().magic;
+
+    let send = test_send(&keypair, TEST_BLOCK.hash(), Balance(100), dest.public.into());
+    let second = test_send(&keypair, send.hash(), Balance(50), dest.public.into());
+    let open = test_open(&dest, send.hash());
+    let receive = test_receive(&dest, open.hash(), second.hash());
+
+    let mut remote = Storage::new_test();
+    for tx in vec![send.clone(), second.clone()] {
+        remote.insert(tx.into()).unwrap();
+    }
+    for tx in vec![open.into(), receive.clone().into()] {
+        remote.insert(tx).unwrap();
+    }
+    let remote = Arc::new(Mutex::new(remote));
+    let server = BootstrapServer::start("127.0.0.1:0", remote.clone(), magic).unwrap();
+
+    // The local ledger already has the first send, so only the rest is pulled
+    let mut local = Storage::new_test();
+    local.insert(send.clone().into()).unwrap();
+    let mut client = BootstrapClient::connect(server.local_addr(), magic).unwrap();
+    let mut frontiers = client.frontiers(PubKey([0; 32]), u32::max_value()).unwrap();
+    frontiers.sort();
+    let mut expected = vec![(genesis, second.hash()), (dest.public.into(), receive.hash())];
+    expected.sort();
+    assert_eq!(frontiers, expected);
+    let pulled = client.bulk_pull(Hash(genesis.0), send.hash()).unwrap();
+    assert_eq!(pulled.len(), 1);
+
+    let mut importer = Importer::new(local, 2);
+    let summary = client.sync(&mut importer).unwrap();
+    assert_eq!(summary.inserted, 3);
+    assert!(summary.rejected.is_empty());
+    let mut local = importer.into_storage();
+    for &account in &[genesis, dest.public.into()] {
+        assert_eq!(local.find_head(account), remote.lock().unwrap().find_head(account));
+    }
+
+    // Once in sync there is nothing left to pull
+    let mut importer = Importer::new(local, 2);
+    assert_eq!(client.sync(&mut importer).unwrap().inserted, 0);
+
+    // Frontiers can't be filtered by age, so the request is refused and the connection closed
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    let req = Message::FrontierReq(FrontierReq {
+        start: PubKey([0; 32]),
+        age: 60,
+        count: 10,
+    });
+    stream.write_all(&req.to_bytes(magic).unwrap()).unwrap();
+    assert_eq!(stream.read(&mut [0; 64]).unwrap(), 0);
+
+    // Dropping the server closes the connections it is still serving
+    drop(server);
+    assert!(client.frontiers(PubKey([0; 32]), 1).is_err());
+}
+
+#[test]
+fn test_node_flooding() {
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use node::Node;
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let magic = Storage::new_test().network().magic;
+
+    // Three nodes in a line, the ends only know about the middle
+    let nodes: Vec<Node<Storage>> = (0..3)
+        .map(|_| {
+            let storage = Arc::new(Mutex::new(Storage::new_test()));
+            Node::start("127.0.0.1:0", storage, magic).unwrap()
+        })
+        .collect();
+    nodes[0].add_peer(nodes[1].local_addr().unwrap());
+    nodes[1].add_peer(nodes[2].local_addr().unwrap());
+    nodes[2].add_peer(nodes[1].local_addr().unwrap());
+    let converged = |hash: Hash| {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if nodes.iter().all(|n| n.storage().lock().unwrap().lookup(hash).is_some()) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    };
+
+    let send = test_send(&keypair, TEST_BLOCK.hash(), Balance(100), dest.public.into());
+    nodes[0].publish(send.clone().into()).unwrap();
+    assert!(converged(send.hash()));
+    // The middle node learned about the first one when it received the block
+    let open = test_open(&dest, send.hash());
+    nodes[2].publish(open.clone().into()).unwrap();
+    assert!(converged(open.hash()));
+    assert_eq!(nodes[2].publish(open.clone().into()).unwrap_err(), Failure::Duplicate);
+    for n in &nodes {
+        let mut s = n.storage().lock().unwrap();
+        assert_eq!(s.find_head(dest.public.into()), Some(open.hash()));
+        assert_eq!(s.find_balance(send.hash()), Some(Balance(100)));
+    }
+}
+
+#[test]
+fn test_node_keepalive() {
+    use std::net::UdpSocket;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use node::Node;
+    use protocol::Message;
+    let magic = Storage::new_test().network().magic;
+    let nodes: Vec<Node<Storage>> = (0..3)
+        .map(|_| {
+            let storage = Arc::new(Mutex::new(Storage::new_test()));
+            Node::start("127.0.0.1:0", storage, magic).unwrap()
+        })
+        .collect();
+    let addrs: Vec<_> = nodes.iter().map(|n| n.local_addr().unwrap()).collect();
+    // The ends only know about the middle, and learn about each other through it
+    assert!(nodes[0].add_peer(addrs[1]));
+    assert!(nodes[2].add_peer(addrs[1]));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    // Every node ends up knowing every other one, along with its node ID
+    let knows = |i: usize, j: usize| {
+        let table = nodes[i].peer_table().lock().unwrap();
+        table.find_peer(addrs[j]).and_then(|p| p.node_id) == Some(nodes[j].node_id())
+    };
+    while !(0..3).all(|i| (0..3).all(|j| i == j || knows(i, j))) {
+        assert!(Instant::now() < deadline, "Peers didn't converge");
+        for node in &nodes {
+            node.keepalive();
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        let mut peers = node.peers();
+        peers.sort();
+        let mut expected: Vec<_> = addrs.iter().cloned().filter(|&a| a != addrs[i]).collect();
+        expected.sort();
+        assert_eq!(peers, expected);
+    }
+    let table = nodes[1].peer_table().lock().unwrap().clone();
+    assert_eq!(table.find_peer(addrs[0]).unwrap().version, 13);
+    assert_eq!(nodes[1].purge(Instant::now() + Duration::from_secs(1)).len(), 2);
+    assert!(nodes[1].peers().is_empty());
+
+    // Endpoints listed in a keepalive are only contacted by the next periodic keepalive
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let listed = UdpSocket::bind("127.0.0.1:0").unwrap();
+    listed.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let keepalive = Message::Keepalive(vec![listed.local_addr().unwrap()]);
+    sender.send_to(&keepalive.to_bytes(magic).unwrap(), addrs[1]).unwrap();
+    let mut buf = [0; 512];
+    assert!(listed.recv_from(&mut buf).is_err());
+    // Wait for the sender to become a peer, so the keepalive has been processed
+    sender.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    sender.recv_from(&mut buf).unwrap();
+    nodes[1].keepalive();
+    listed.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let (len, from) = listed.recv_from(&mut buf).unwrap();
+    assert_eq!(from, addrs[1]);
+    match Message::from_bytes(magic, &buf[..len]).unwrap().1 {
+        Message::Keepalive(_) => {}
+        m => panic!("Unexpected message {:?}", m),
+    }
+}
+
+#[test]
+fn test_rpc() {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use serde_json::{self, Value};
+    use rpc::{block_to_json, RpcServer};
+    fn call(addr: SocketAddr, request: Value) -> Value {
+        let body = request.to_string();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let start = response.find("\r\n\r\n").unwrap() + 4;
+        serde_json::from_str(&response[start..]).unwrap()
+    }
+    let keypair = test_keypair();
+    let dest = test_dest();
+    let genesis = TEST_BLOCK.account.to_address();
+    let dest_address = PubKey::from(dest.public).to_address();
+    let storage = Arc::new(Mutex::new(Storage::new_test()));
+    let server = RpcServer::start("127.0.0.1:0", storage.clone(), 2).unwrap();
+    let addr = server.local_addr();
+
+    let balance = call(addr, json!({ "action": "account_balance", "account": genesis }));
+    assert_eq!(balance, json!({ "balance": BALANCE.0.to_string(), "pending": "0" }));
+    let info = call(addr, json!({ "action": "account_info", "account": dest_address }));
+    assert_eq!(info, json!({ "error": "Account not found" }));
+    let unknown = call(addr, json!({ "action": "launch_missiles" }));
+    assert_eq!(unknown, json!({ "error": "Unknown command" }));
+
+    // Work for a send is computed over the previous block
+    let difficulty = format!("{:016X}", Difficulty::TEST.send);
+    let root = TEST_BLOCK.hash().to_string();
+    let generated = call(
+        addr,
+        json!({ "action": "work_generate", "hash": root, "difficulty": difficulty }),
+    );
+    let work: Work = generated["work"].as_str().unwrap().parse().unwrap();
+    let valid = call(
+        addr,
+        json!({ "action": "work_validate", "hash": root, "work": generated["work"] }),
+    );
+    assert_eq!(valid["valid"], "1");
+    assert_eq!(valid["difficulty"], generated["difficulty"]);
+    let other = Hash([1; 32]).to_string();
+    let invalid = call(
+        addr,
+        json!({ "action": "work_validate", "hash": other, "work": "0000000000000000" }),
+    );
+    assert_eq!(invalid["valid"], "0");
+    // Thresholds that could keep the server busy indefinitely are refused
+    let too_hard = call(
+        addr,
+        json!({ "action": "work_generate", "hash": root, "difficulty": "FFFFFFFFFFFFFFFF" }),
+    );
+    assert_eq!(too_hard, json!({ "error": "Difficulty above config max" }));
+    // So are bodies larger than the request limit, before anything is allocated for them
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "POST / HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+    let mut send = SendTransaction::new_without_work(
+        &keypair,
+        TEST_BLOCK.hash(),
+        Balance(100),
+        dest.public.into(),
+    );
+    send.work = work;
+    let network = storage.lock().unwrap().network().clone();
+    let block = block_to_json(&network, &send.clone().into()).to_string();
+    let processed = call(addr, json!({ "action": "process", "block": block }));
+    assert_eq!(processed, json!({ "hash": send.hash().to_string() }));
+    let duplicate = call(addr, json!({ "action": "process", "block": block }));
+    assert_eq!(duplicate, json!({ "error": "Old block" }));
+    assert_eq!(storage.lock().unwrap().find_head(TEST_BLOCK.account), Some(send.hash()));
+
+    let amount = (BALANCE - Balance(100)).0.to_string();
+    let pending = call(
+        addr,
+        json!({ "action": "pending", "account": dest_address, "source": "true" }),
+    );
+    let mut blocks = serde_json::Map::new();
+    blocks.insert(
+        send.hash().to_string(),
+        json!({ "amount": amount, "source": genesis }),
+    );
+    assert_eq!(pending, json!({ "blocks": blocks }));
+    let balance = call(addr, json!({ "action": "account_balance", "account": dest_address }));
+    assert_eq!(balance, json!({ "balance": "0", "pending": amount }));
+
+    let block_info = call(addr, json!({ "action": "block_info", "hash": send.hash().to_string() }));
+    assert_eq!(block_info["block_account"], genesis);
+    assert_eq!(block_info["amount"], amount);
+    assert_eq!(block_info["balance"], "100");
+    assert_eq!(block_info["height"], "2");
+    assert_eq!(block_info["confirmed"], "false");
+    assert_eq!(block_info["contents"]["destination"], dest_address);
+
+    let info = call(addr, json!({ "action": "account_info", "account": genesis }));
+    assert_eq!(info["frontier"], send.hash().to_string());
+    assert_eq!(info["block_count"], "2");
+    assert_eq!(info["confirmation_height"], "1");
+    let history = call(
+        addr,
+        json!({ "action": "account_history", "account": genesis, "count": "1" }),
+    );
+    assert_eq!(history["history"].as_array().unwrap().len(), 1);
+    assert_eq!(history["history"][0]["type"], "send");
+    assert_eq!(history["history"][0]["account"], dest_address);
+    assert_eq!(history["history"][0]["amount"], amount);
+    assert_eq!(history["previous"], root);
+}
@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::thread;
 use ed25519_dalek as ed25519;
 use blake2::Blake2b;
 use blake2::digest::{Input, VariableOutput};
@@ -6,7 +8,7 @@ use blake2::digest::{Input, VariableOutput};
 use types::{Balance, Hash, PubKey, Signature, Work, WorkHash};
 use errors::Failure;
 use blockstorage::BlockStorage;
-use work::compute_work;
+use work::{compute_work, work_hash, Difficulty};
 
 pub trait RaiHash {
     fn hash(&self) -> Hash;
@@ -22,7 +24,7 @@ pub trait RaiHashImpl<'a> {
             hash.process(e)
         }
         let mut bytes = Hash::default();
-        hash.variable_result(&mut bytes).expect("Unreachable");
+        hash.variable_result(&mut bytes.0).expect("Unreachable");
         bytes
     }
 }
@@ -36,8 +38,8 @@ impl<T: for<'a> RaiHashImpl<'a>> RaiHash for T {
 }
 
 pub trait RaiWork {
-    fn verify_work(&self) -> Result<(), Failure> {
-        if self.work_validate().verify() {
+    fn verify_work(&self, threshold: u64) -> Result<(), Failure> {
+        if self.work_validate().verify_threshold(threshold) {
             Ok(())
         } else {
             Err(Failure::Work)
@@ -45,18 +47,15 @@ pub trait RaiWork {
     }
     fn work_validate(&self) -> WorkHash;
     fn work_calculate(&self, Work) -> WorkHash;
+    /// The value the work for this transaction is computed over
+    fn work_root(&self) -> Hash;
 }
 
 pub trait RaiWorkImpl {
     fn work_element(&self) -> &[u8];
     fn work_value(&self) -> Work;
     fn work_impl(&self, work: Work) -> WorkHash {
-        let mut hash = Blake2b::new(8).expect("Unreachable");
-        hash.process(work.as_ref());
-        hash.process(self.work_element());
-        let mut bytes = <[u8; 8]>::default();
-        hash.variable_result(&mut bytes).expect("Unreachable");
-        WorkHash(bytes)
+        work_hash(self.work_element(), work)
     }
 }
 
@@ -67,14 +66,20 @@ impl<T: RaiWorkImpl> RaiWork for T {
     fn work_calculate(&self, work: Work) -> WorkHash {
         self.work_impl(work)
     }
+    fn work_root(&self) -> Hash {
+        let mut root = Hash::default();
+        root.0.copy_from_slice(self.work_element());
+        root
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Transaction {
     Open(OpenTransaction),
     Send(SendTransaction),
     Receive(ReceiveTransaction),
     Change(ChangeTransaction),
+    State(StateTransaction),
 }
 
 impl Transaction {
@@ -86,6 +91,203 @@ impl Transaction {
             &Send(ref s) => s.verify(storage),
             &Receive(ref r) => r.verify(storage),
             &Change(ref c) => c.verify(storage),
+            &State(ref s) => s.verify(storage),
+        }
+    }
+    /// Verify everything but this transaction's signature, for when it has already been checked
+    pub(crate) fn verify_without_sig<S: BlockStorage>(
+        &self,
+        storage: &mut S,
+    ) -> Result<(), Failure> {
+        use transaction::Transaction::*;
+        match self {
+            &Open(ref o) => {
+                o.verify_work(storage.difficulty().receive)?;
+                o.verify_parent(storage)
+            }
+            &Send(ref s) => {
+                s.verify_work(storage.difficulty().send)?;
+                s.verify_balance(storage)
+            }
+            &Receive(ref r) => {
+                r.verify_work(storage.difficulty().receive)?;
+                let pubkey = storage.find_key(r.previous).ok_or(Failure::Missing)?;
+                r.verify_parent(storage, pubkey)
+            }
+            &Change(ref c) => c.verify_work(storage.difficulty().send),
+            &State(ref s) => s.verify_kind_work(storage),
+        }
+    }
+    /// Verify only this transaction's signature, without checking it against the ledger
+    pub(crate) fn verify_sig<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
+        use transaction::Transaction::*;
+        match self {
+            &Open(ref o) => o.verify_sig(),
+            &Send(ref s) => s.verify_sig(storage).map(|_| ()),
+            &Receive(ref r) => r.verify_sig(storage).map(|_| ()),
+            &Change(ref c) => c.verify_sig(storage),
+            &State(ref s) => s.verify_sig(),
+        }
+    }
+    /// Verify only this transaction's signature against a signer that is already known
+    pub(crate) fn verify_sig_with(&self, key: PubKey) -> Result<(), Failure> {
+        verify_signature(key, self.hash().as_ref(), self.signature())
+    }
+    pub fn signature(&self) -> Signature {
+        use transaction::Transaction::*;
+        match self {
+            &Open(ref o) => o.signature,
+            &Send(ref s) => s.signature,
+            &Receive(ref r) => r.signature,
+            &Change(ref c) => c.signature,
+            &State(ref s) => s.signature,
+        }
+    }
+    /// The block this transaction follows, or `None` if it opens an account
+    pub fn previous(&self) -> Option<Hash> {
+        use transaction::Transaction::*;
+        match self {
+            &Open(_) => None,
+            &Send(ref s) => Some(s.previous),
+            &Receive(ref r) => Some(r.previous),
+            &Change(ref c) => Some(c.previous),
+            &State(ref s) if s.is_open() => None,
+            &State(ref s) => Some(s.previous),
+        }
+    }
+    /// The position this transaction occupies in its account chain, two transactions with the
+    /// same root are a fork
+    ///
+    /// This is the previous block, or the account itself for an open block.
+    pub fn root(&self) -> Hash {
+        use transaction::Transaction::*;
+        match self {
+            &Open(ref o) => Hash(o.account.0),
+            &State(ref s) if s.is_open() => Hash(s.account.0),
+            _ => self.previous().expect("Unreachable"),
+        }
+    }
+}
+
+/// Verify the signatures of many transactions, spread across `threads` threads
+///
+/// Signers are looked up in `storage`, or taken from other transactions in the slice in any order,
+/// so the slice can contain account chains that haven't been inserted yet. Each signature is then
+/// checked on its own with `Transaction::verify_sig_with`; this is not Ed25519 batch verification,
+/// which the ed25519-dalek fork we depend on doesn't offer for Blake2b signatures. Returns the
+/// indices of the transactions that failed in ascending order, with `Missing` if the signer
+/// couldn't be found or `Signature` if the signature is invalid.
+pub fn verify_parallel<S: BlockStorage>(
+    storage: &mut S,
+    txs: &[Transaction],
+    threads: usize,
+) -> Vec<(usize, Failure)> {
+    use transaction::Transaction::*;
+    let hashes: Vec<Hash> = txs.iter().map(|t| t.hash()).collect();
+    let index: HashMap<Hash, usize> = hashes.iter().enumerate().map(|(i, &h)| (h, i)).collect();
+    let mut keys: HashMap<Hash, PubKey> = HashMap::new();
+    let mut jobs = Vec::with_capacity(txs.len());
+    let mut failed = Vec::new();
+    for i in 0..txs.len() {
+        // Walk back through the slice until the signer is known
+        let mut chain = vec![i];
+        let key = loop {
+            let current = *chain.last().expect("Unreachable");
+            if let Some(&key) = keys.get(&hashes[current]) {
+                break Some(key);
+            }
+            let previous = match txs[current] {
+                Open(ref o) => break Some(o.account),
+                State(ref s) => break Some(s.account),
+                _ => txs[current].previous().expect("Unreachable"),
+            };
+            match index.get(&previous) {
+                Some(&j) => chain.push(j),
+                None => break storage.find_key(previous),
+            }
+        };
+        match key {
+            Some(key) => {
+                for &j in &chain {
+                    keys.insert(hashes[j], key);
+                }
+                jobs.push((i, (txs[i].clone(), key)));
+            }
+            None => failed.push((i, Failure::Missing)),
+        }
+    }
+    failed.extend(check_parallel(jobs, threads, |(tx, key): (Transaction, PubKey)| {
+        tx.verify_sig_with(key)
+    }));
+    failed.sort_by_key(|&(i, _)| i);
+    failed
+}
+
+/// Run `check` over each job, spread across `threads` threads, and collect the failures
+pub(crate) fn check_parallel<T, F>(
+    mut jobs: Vec<(usize, T)>,
+    threads: usize,
+    check: F,
+) -> Vec<(usize, Failure)>
+where
+    T: Send + 'static,
+    F: Fn(T) -> Result<(), Failure> + Copy + Send + 'static,
+{
+    let threads = threads.max(1);
+    let chunk = ((jobs.len() + threads - 1) / threads).max(1);
+    let mut handles = Vec::with_capacity(threads);
+    while !jobs.is_empty() {
+        let at = jobs.len().saturating_sub(chunk);
+        let batch = jobs.split_off(at);
+        handles.push(thread::spawn(move || {
+            batch
+                .into_iter()
+                .filter_map(|(i, job)| check(job).err().map(|e| (i, e)))
+                .collect::<Vec<_>>()
+        }));
+    }
+    let mut failed = Vec::new();
+    for h in handles {
+        failed.extend(h.join().expect("Unreachable"));
+    }
+    failed
+}
+
+/// Check a signature over `message` made by `key`
+///
+/// Every signature check, whether of a transaction, a vote or a node ID, goes through here.
+pub(crate) fn verify_signature(
+    key: PubKey,
+    message: &[u8],
+    signature: Signature,
+) -> Result<(), Failure> {
+    let pubkey: ed25519::PublicKey = key.try_into()?;
+    let sig = signature.try_into()?;
+    match pubkey.verify::<Blake2b>(message, &sig) {
+        true => Ok(()),
+        false => Err(Failure::Signature),
+    }
+}
+
+impl RaiWorkImpl for Transaction {
+    fn work_element(&self) -> &[u8] {
+        use transaction::Transaction::*;
+        match self {
+            &Open(ref o) => o.work_element(),
+            &Send(ref s) => s.work_element(),
+            &Receive(ref r) => r.work_element(),
+            &Change(ref c) => c.work_element(),
+            &State(ref s) => s.work_element(),
+        }
+    }
+    fn work_value(&self) -> Work {
+        use transaction::Transaction::*;
+        match self {
+            &Open(ref o) => o.work,
+            &Send(ref s) => s.work,
+            &Receive(ref r) => r.work,
+            &Change(ref c) => c.work,
+            &State(ref s) => s.work,
         }
     }
 }
@@ -98,11 +300,12 @@ impl RaiHash for Transaction {
             &Send(ref s) => s.hash(),
             &Receive(ref r) => r.hash(),
             &Change(ref c) => c.hash(),
+            &State(ref s) => s.hash(),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OpenTransaction {
     pub account: PubKey,
     pub source: Hash,
@@ -114,7 +317,7 @@ pub struct OpenTransaction {
 impl OpenTransaction {
     pub fn new(key: &ed25519::Keypair, source: Hash, rep: Option<PubKey>) -> Self {
         let mut o = Self::new_without_work(key, source, rep);
-        o.work = compute_work(&o);
+        o.work = compute_work(&o, Difficulty::LIVE.receive);
         o
     }
     pub fn new_without_work(key: &ed25519::Keypair, source: Hash, rep: Option<PubKey>) -> Self {
@@ -125,24 +328,18 @@ impl OpenTransaction {
             work: Work::default(),
             signature: Signature::default(),
         };
-        o.signature = key.sign::<Blake2b>(&o.hash()).to_bytes().into();
+        o.signature = key.sign::<Blake2b>(o.hash().as_ref()).to_bytes().into();
         o
     }
     pub fn verify<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
         self.verify_sig()?;
-        self.verify_work()?;
+        self.verify_work(storage.difficulty().receive)?;
         self.verify_parent(storage)
     }
     pub(crate) fn verify_parent<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
-        {
-            let source = storage.lookup(self.source).ok_or(Failure::Missing)?;
-            let source = match source {
-                &Transaction::Send(ref s) => s,
-                _ => return Err(Failure::Invalid),
-            };
-            if source.destination != self.account {
-                return Err(Failure::Invalid);
-            }
+        let (destination, _) = storage.find_send(self.source)?;
+        if destination != self.account {
+            return Err(Failure::Invalid);
         }
         if storage.is_unspent(self.source) {
             Ok(())
@@ -151,19 +348,14 @@ impl OpenTransaction {
         }
     }
     pub(crate) fn verify_sig(&self) -> Result<(), Failure> {
-        let pubkey: ed25519::PublicKey = self.account.try_into()?;
-        let sig = self.signature.try_into()?;
-        match pubkey.verify::<Blake2b>(&self.hash(), &sig) {
-            true => Ok(()),
-            false => Err(Failure::Signature),
-        }
+        verify_signature(self.account, self.hash().as_ref(), self.signature)
     }
 }
 impl<'a> RaiHashImpl<'a> for OpenTransaction {
     type Elements = [&'a [u8]; 3];
     fn hash_elements(&'a self) -> [&'a [u8]; 3] {
         [
-            &self.source,
+            self.source.as_ref(),
             self.representative.as_ref(),
             self.account.as_ref(),
         ]
@@ -185,7 +377,7 @@ impl Into<Transaction> for OpenTransaction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SendTransaction {
     pub previous: Hash,
     pub balance: Balance,
@@ -202,7 +394,7 @@ impl SendTransaction {
         destination: PubKey,
     ) -> Self {
         let mut o = Self::new_without_work(key, previous, balance, destination);
-        o.work = compute_work(&o);
+        o.work = compute_work(&o, Difficulty::LIVE.send);
         o
     }
     pub fn new_without_work(
@@ -218,22 +410,18 @@ impl SendTransaction {
             work: Work::default(),
             signature: Signature::default(),
         };
-        o.signature = key.sign::<Blake2b>(&o.hash()).into();
+        o.signature = key.sign::<Blake2b>(o.hash().as_ref()).into();
         o
     }
     pub(crate) fn verify<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
-        self.verify_work()?;
+        self.verify_work(storage.difficulty().send)?;
         let pubkey = self.verify_sig(storage)?;
         self.verify_balance(storage)
     }
     pub(crate) fn verify_sig<S: BlockStorage>(&self, storage: &mut S) -> Result<PubKey, Failure> {
-        let pubkey_bytes = storage.find_key(self.previous).ok_or(Failure::Missing)?;
-        let pubkey: ed25519::PublicKey = pubkey_bytes.try_into()?;
-        let sig = self.signature.try_into()?;
-        match pubkey.verify::<Blake2b>(&self.hash(), &sig) {
-            true => Ok(pubkey_bytes),
-            false => Err(Failure::Signature),
-        }
+        let pubkey = storage.find_key(self.previous).ok_or(Failure::Missing)?;
+        verify_signature(pubkey, self.hash().as_ref(), self.signature)?;
+        Ok(pubkey)
     }
     pub(crate) fn verify_balance<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
         let bal = storage
@@ -251,7 +439,7 @@ impl<'a> RaiHashImpl<'a> for SendTransaction {
     type Elements = [&'a [u8]; 3];
     fn hash_elements(&'a self) -> [&'a [u8]; 3] {
         [
-            &self.previous,
+            self.previous.as_ref(),
             self.destination.as_ref(),
             self.balance.as_ref(),
         ]
@@ -260,7 +448,7 @@ impl<'a> RaiHashImpl<'a> for SendTransaction {
 
 impl RaiWorkImpl for SendTransaction {
     fn work_element(&self) -> &[u8] {
-        &self.previous
+        self.previous.as_ref()
     }
     fn work_value(&self) -> Work {
         self.work
@@ -273,7 +461,7 @@ impl Into<Transaction> for SendTransaction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ReceiveTransaction {
     pub previous: Hash,
     pub source: Hash,
@@ -284,7 +472,7 @@ pub struct ReceiveTransaction {
 impl ReceiveTransaction {
     pub fn new(key: &ed25519::Keypair, previous: Hash, source: Hash) -> Self {
         let mut o = Self::new_without_work(key, previous, source);
-        o.work = compute_work(&o);
+        o.work = compute_work(&o, Difficulty::LIVE.receive);
         o
     }
     pub fn new_without_work(key: &ed25519::Keypair, previous: Hash, source: Hash) -> Self {
@@ -294,11 +482,11 @@ impl ReceiveTransaction {
             work: Work::default(),
             signature: Signature::default(),
         };
-        o.signature = key.sign::<Blake2b>(&o.hash()).into();
+        o.signature = key.sign::<Blake2b>(o.hash().as_ref()).into();
         o
     }
     pub(crate) fn verify<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
-        self.verify_work()?;
+        self.verify_work(storage.difficulty().receive)?;
         let pubkey = self.verify_sig(storage)?;
         self.verify_parent(storage, pubkey)
     }
@@ -307,15 +495,9 @@ impl ReceiveTransaction {
         storage: &mut S,
         pubkey: PubKey,
     ) -> Result<(), Failure> {
-        {
-            let source = storage.lookup(self.source).ok_or(Failure::Missing)?;
-            let source = match source {
-                &Transaction::Send(ref s) => s,
-                _ => return Err(Failure::Invalid),
-            };
-            if source.destination != pubkey {
-                return Err(Failure::Invalid);
-            }
+        let (destination, _) = storage.find_send(self.source)?;
+        if destination != pubkey {
+            return Err(Failure::Invalid);
         }
         if storage.is_unspent(self.source) {
             Ok(())
@@ -324,26 +506,22 @@ impl ReceiveTransaction {
         }
     }
     pub(crate) fn verify_sig<S: BlockStorage>(&self, storage: &mut S) -> Result<PubKey, Failure> {
-        let pubkey_bytes = storage.find_key(self.previous).ok_or(Failure::Missing)?;
-        let pubkey: ed25519::PublicKey = pubkey_bytes.try_into()?;
-        let sig = self.signature.try_into()?;
-        match pubkey.verify::<Blake2b>(&self.hash(), &sig) {
-            true => Ok(pubkey_bytes),
-            false => Err(Failure::Signature),
-        }
+        let pubkey = storage.find_key(self.previous).ok_or(Failure::Missing)?;
+        verify_signature(pubkey, self.hash().as_ref(), self.signature)?;
+        Ok(pubkey)
     }
 }
 
 impl<'a> RaiHashImpl<'a> for ReceiveTransaction {
     type Elements = [&'a [u8]; 2];
     fn hash_elements(&'a self) -> [&'a [u8]; 2] {
-        [&self.previous, &self.source]
+        [self.previous.as_ref(), self.source.as_ref()]
     }
 }
 
 impl RaiWorkImpl for ReceiveTransaction {
     fn work_element(&self) -> &[u8] {
-        &self.previous
+        self.previous.as_ref()
     }
     fn work_value(&self) -> Work {
         self.work
@@ -356,7 +534,7 @@ impl Into<Transaction> for ReceiveTransaction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChangeTransaction {
     pub previous: Hash,
     pub representative: PubKey,
@@ -367,7 +545,7 @@ pub struct ChangeTransaction {
 impl ChangeTransaction {
     pub fn new(key: &ed25519::Keypair, previous: Hash, rep: PubKey) -> Self {
         let mut o = Self::new_without_work(key, previous, rep);
-        o.work = compute_work(&o);
+        o.work = compute_work(&o, Difficulty::LIVE.send);
         o
     }
     pub fn new_without_work(key: &ed25519::Keypair, previous: Hash, rep: PubKey) -> Self {
@@ -377,34 +555,29 @@ impl ChangeTransaction {
             work: Work::default(),
             signature: Signature::default(),
         };
-        o.signature = key.sign::<Blake2b>(&o.hash()).into();
+        o.signature = key.sign::<Blake2b>(o.hash().as_ref()).into();
         o
     }
     pub(crate) fn verify<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
         self.verify_sig(storage)?;
-        self.verify_work()
+        self.verify_work(storage.difficulty().send)
     }
     pub(crate) fn verify_sig<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
-        let pubkey_bytes = storage.find_key(self.previous).ok_or(Failure::Missing)?;
-        let pubkey: ed25519::PublicKey = pubkey_bytes.try_into()?;
-        let sig = self.signature.try_into()?;
-        match pubkey.verify::<Blake2b>(&self.hash(), &sig) {
-            true => Ok(()),
-            false => Err(Failure::Signature),
-        }
+        let pubkey = storage.find_key(self.previous).ok_or(Failure::Missing)?;
+        verify_signature(pubkey, self.hash().as_ref(), self.signature)
     }
 }
 
 impl<'a> RaiHashImpl<'a> for ChangeTransaction {
     type Elements = [&'a [u8]; 2];
     fn hash_elements(&'a self) -> [&'a [u8]; 2] {
-        [&self.previous, self.representative.as_ref()]
+        [self.previous.as_ref(), self.representative.as_ref()]
     }
 }
 
 impl RaiWorkImpl for ChangeTransaction {
     fn work_element(&self) -> &[u8] {
-        &self.previous
+        self.previous.as_ref()
     }
     fn work_value(&self) -> Work {
         self.work
@@ -416,3 +589,153 @@ impl Into<Transaction> for ChangeTransaction {
         Transaction::Change(self)
     }
 }
+
+/// The preamble hashed in front of every state block, distinguishing it from legacy blocks
+const STATE_PREAMBLE: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 6,
+];
+
+/// The operation a state block performs, inferred from its balance and link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateKind {
+    /// The first block of an account, receiving the send referenced by `link`
+    Open,
+    /// The balance decreased, `link` is the destination account
+    Send,
+    /// The balance increased, `link` is the hash of the send being received
+    Receive,
+    /// The balance is unchanged, only the representative may differ
+    Change,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateTransaction {
+    pub account: PubKey,
+    pub previous: Hash,
+    pub representative: PubKey,
+    pub balance: Balance,
+    pub link: Hash,
+    pub work: Work,
+    pub signature: Signature,
+}
+
+impl StateTransaction {
+    pub fn new(
+        key: &ed25519::Keypair,
+        previous: Hash,
+        representative: PubKey,
+        balance: Balance,
+        link: Hash,
+    ) -> Self {
+        let mut o = Self::new_without_work(key, previous, representative, balance, link);
+        // The block's kind depends on the ledger, so use work that is valid for any kind
+        o.work = compute_work(&o, Difficulty::LIVE.highest());
+        o
+    }
+    pub fn new_without_work(
+        key: &ed25519::Keypair,
+        previous: Hash,
+        representative: PubKey,
+        balance: Balance,
+        link: Hash,
+    ) -> Self {
+        let mut o = Self {
+            account: key.public.to_bytes().into(),
+            previous,
+            representative,
+            balance,
+            link,
+            work: Work::default(),
+            signature: Signature::default(),
+        };
+        o.signature = key.sign::<Blake2b>(o.hash().as_ref()).into();
+        o
+    }
+    /// A state block with no previous block opens its account
+    pub fn is_open(&self) -> bool {
+        self.previous == Hash::default()
+    }
+    pub(crate) fn verify<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
+        self.verify_sig()?;
+        self.verify_kind_work(storage)
+    }
+    /// Check the work against the threshold for the operation this block performs
+    pub(crate) fn verify_kind_work<S: BlockStorage>(&self, storage: &mut S) -> Result<(), Failure> {
+        let threshold = match self.kind(storage)? {
+            StateKind::Send | StateKind::Change => storage.difficulty().send,
+            StateKind::Open | StateKind::Receive => storage.difficulty().receive,
+        };
+        self.verify_work(threshold)
+    }
+    /// Work out which operation this block performs, checking it against the ledger
+    pub fn kind<S: BlockStorage>(&self, storage: &mut S) -> Result<StateKind, Failure> {
+        let prev_bal = if self.is_open() {
+            Balance(0)
+        } else {
+            if storage.find_key(self.previous).ok_or(Failure::Missing)? != self.account {
+                return Err(Failure::Invalid);
+            }
+            storage
+                .find_balance(self.previous)
+                .ok_or(Failure::Unreachable)?
+        };
+        if self.balance < prev_bal {
+            return Ok(StateKind::Send);
+        }
+        if self.balance == prev_bal {
+            return if self.is_open() || self.link != Hash::default() {
+                Err(Failure::Invalid)
+            } else {
+                Ok(StateKind::Change)
+            };
+        }
+        let (destination, amount) = storage.find_send(self.link)?;
+        if destination != self.account || amount != self.balance - prev_bal {
+            return Err(Failure::Invalid);
+        }
+        if !storage.is_unspent(self.link) {
+            return Err(Failure::Received);
+        }
+        if self.is_open() {
+            Ok(StateKind::Open)
+        } else {
+            Ok(StateKind::Receive)
+        }
+    }
+    pub(crate) fn verify_sig(&self) -> Result<(), Failure> {
+        verify_signature(self.account, self.hash().as_ref(), self.signature)
+    }
+}
+
+impl<'a> RaiHashImpl<'a> for StateTransaction {
+    type Elements = [&'a [u8]; 6];
+    fn hash_elements(&'a self) -> [&'a [u8]; 6] {
+        [
+            &STATE_PREAMBLE,
+            self.account.as_ref(),
+            self.previous.as_ref(),
+            self.representative.as_ref(),
+            self.balance.as_ref(),
+            self.link.as_ref(),
+        ]
+    }
+}
+
+impl RaiWorkImpl for StateTransaction {
+    fn work_element(&self) -> &[u8] {
+        if self.is_open() {
+            self.account.as_ref()
+        } else {
+            self.previous.as_ref()
+        }
+    }
+    fn work_value(&self) -> Work {
+        self.work
+    }
+}
+
+impl Into<Transaction> for StateTransaction {
+    fn into(self) -> Transaction {
+        Transaction::State(self)
+    }
+}
@@ -1,16 +1,191 @@
 use std;
+use std::fmt;
 use std::convert::TryInto;
+use std::str::FromStr;
 use byteorder::{ByteOrder, BE, LE};
 use rand::{Rand, Rng};
 use ed25519_dalek as ed25519;
+use blake2::Blake2b;
+use blake2::digest::{Input, VariableOutput};
 
-use errors::Failure;
+use errors::{AddressError, Failure, HexError};
 
-pub type Hash = [u8; 32];
+fn write_hex(bytes: &[u8], fmt: &mut fmt::Formatter) -> fmt::Result {
+    for b in bytes {
+        write!(fmt, "{:02X}", b)?;
+    }
+    Ok(())
+}
+
+fn hex_digit(c: u8) -> Result<u8, HexError> {
+    match c {
+        b'0'...b'9' => Ok(c - b'0'),
+        b'a'...b'f' => Ok(c - b'a' + 10),
+        b'A'...b'F' => Ok(c - b'A' + 10),
+        _ => Err(HexError::Character),
+    }
+}
+
+/// Parse a hex string of exactly `out.len()` bytes, in either case
+fn read_hex(s: &str, out: &mut [u8]) -> Result<(), HexError> {
+    if s.len() != out.len() * 2 {
+        return Err(HexError::Length);
+    }
+    for (b, pair) in out.iter_mut().zip(s.as_bytes().chunks(2)) {
+        *b = hex_digit(pair[0])? << 4 | hex_digit(pair[1])?;
+    }
+    Ok(())
+}
+
+/// Display, Debug and FromStr as uppercase hex for a newtype around a byte array
+macro_rules! hex_impls {
+    ($t:ident, $len:expr) => {
+        impl fmt::Display for $t {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write_hex(&self.0, fmt)
+            }
+        }
+        impl fmt::Debug for $t {
+            fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+                write_hex(&self.0, fmt)
+            }
+        }
+        impl FromStr for $t {
+            type Err = HexError;
+            fn from_str(s: &str) -> Result<$t, HexError> {
+                let mut bytes = [0; $len];
+                read_hex(s, &mut bytes)?;
+                Ok($t(bytes))
+            }
+        }
+    };
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Default)]
+pub struct Hash(pub [u8; 32]);
+
+hex_impls!(Hash, 32);
+
+impl From<[u8; 32]> for Hash {
+    fn from(hash: [u8; 32]) -> Hash {
+        Hash(hash)
+    }
+}
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+impl AsRef<[u8; 32]> for Hash {
+    fn as_ref(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Hash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
 pub struct PubKey(pub(crate) [u8; 32]);
 
+hex_impls!(PubKey, 32);
+
+/// The base32 alphabet used by account addresses, it omits easily confused characters
+const ADDRESS_ALPHABET: &[u8; 32] = b"13456789abcdefghijkmnopqrstuwxyz";
+const ADDRESS_PREFIXES: [&str; 2] = ["xrb_", "nano_"];
+/// 52 characters for the account and 8 for the checksum
+const ADDRESS_LENGTH: usize = 60;
+
+impl PubKey {
+    /// Encode this account as an address with the `xrb_` prefix
+    pub fn to_address(&self) -> String {
+        self.to_address_with_prefix(ADDRESS_PREFIXES[0])
+    }
+    pub fn to_address_with_prefix(&self, prefix: &str) -> String {
+        let mut address = String::with_capacity(prefix.len() + ADDRESS_LENGTH);
+        address.push_str(prefix);
+        base32_encode(&self.0, &mut address);
+        base32_encode(&self.checksum(), &mut address);
+        address
+    }
+    /// Parse an address with either the `xrb_` or the `nano_` prefix
+    pub fn from_address(address: &str) -> Result<PubKey, AddressError> {
+        let prefix = ADDRESS_PREFIXES
+            .iter()
+            .find(|p| address.starts_with(*p))
+            .ok_or(AddressError::Prefix)?;
+        Self::from_address_with_prefix(address, prefix)
+    }
+    /// Parse an address that must start with `prefix`
+    pub fn from_address_with_prefix(address: &str, prefix: &str) -> Result<PubKey, AddressError> {
+        if !address.starts_with(prefix) {
+            return Err(AddressError::Prefix);
+        }
+        let encoded = &address[prefix.len()..];
+        if encoded.len() != ADDRESS_LENGTH || !encoded.is_ascii() {
+            return Err(AddressError::Length);
+        }
+        let (key, checksum) = encoded.as_bytes().split_at(52);
+        let mut pubkey = PubKey([0; 32]);
+        base32_decode(key, &mut pubkey.0)?;
+        let mut expected = [0; 5];
+        base32_decode(checksum, &mut expected)?;
+        if pubkey.checksum() != expected {
+            return Err(AddressError::Checksum);
+        }
+        Ok(pubkey)
+    }
+    /// The first 5 bytes of the account's Blake2b hash, in reverse order
+    fn checksum(&self) -> [u8; 5] {
+        let mut hash = Blake2b::new(5).expect("Unreachable");
+        hash.process(&self.0);
+        let mut bytes = [0; 5];
+        hash.variable_result(&mut bytes).expect("Unreachable");
+        bytes.reverse();
+        bytes
+    }
+}
+
+/// Encode `bytes` as a big-endian bit string, padded with leading zeros to a multiple of 5 bits
+fn base32_encode(bytes: &[u8], out: &mut String) {
+    let bits = bytes.len() * 8;
+    let pad = (5 - bits % 5) % 5;
+    for i in 0..(bits + pad) / 5 {
+        let mut value = 0;
+        for pos in i * 5..i * 5 + 5 {
+            let bit = if pos < pad {
+                0
+            } else {
+                (bytes[(pos - pad) / 8] >> (7 - (pos - pad) % 8)) & 1
+            };
+            value = value << 1 | bit;
+        }
+        out.push(ADDRESS_ALPHABET[value as usize] as char);
+    }
+}
+
+/// The inverse of `base32_encode`, the padding bits must be zero
+fn base32_decode(chars: &[u8], out: &mut [u8]) -> Result<(), AddressError> {
+    let pad = chars.len() * 5 - out.len() * 8;
+    for (i, c) in chars.iter().enumerate() {
+        let value = ADDRESS_ALPHABET
+            .iter()
+            .position(|a| a == c)
+            .ok_or(AddressError::Character)? as u8;
+        for b in 0..5 {
+            let bit = (value >> (4 - b)) & 1;
+            let pos = i * 5 + b;
+            if pos < pad {
+                if bit != 0 {
+                    return Err(AddressError::Character);
+                }
+            } else {
+                out[(pos - pad) / 8] |= bit << (7 - (pos - pad) % 8);
+            }
+        }
+    }
+    Ok(())
+}
+
 impl From<[u8; 32]> for PubKey {
     fn from(key: [u8; 32]) -> PubKey {
         PubKey(key)
@@ -51,11 +226,7 @@ impl Default for Signature {
     }
 }
 
-impl ::std::fmt::Debug for Signature {
-    fn fmt(&self, fmt: &mut ::std::fmt::Formatter) -> Result<(), ::std::fmt::Error> {
-        ::std::fmt::Debug::fmt(self.0.as_ref(), fmt)
-    }
-}
+hex_impls!(Signature, 64);
 
 impl From<[u8; 64]> for Signature {
     fn from(sig: [u8; 64]) -> Signature {
@@ -76,7 +247,7 @@ impl TryInto<ed25519::Signature> for Signature {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Work(pub u64);
 
 impl Default for Work {
@@ -91,6 +262,21 @@ impl Rand for Work {
     }
 }
 
+impl fmt::Display for Work {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:016X}", self.0)
+    }
+}
+
+impl FromStr for Work {
+    type Err = HexError;
+    fn from_str(s: &str) -> Result<Work, HexError> {
+        let mut bytes = [0; 8];
+        read_hex(s, &mut bytes)?;
+        Ok(Work(BE::read_u64(&bytes)))
+    }
+}
+
 impl AsRef<[u8; 8]> for Work {
     fn as_ref(&self) -> &[u8; 8] {
         unsafe { std::mem::transmute(&self.0) }
@@ -102,8 +288,11 @@ pub struct WorkHash(pub [u8; 8]);
 impl WorkHash {
     pub const RAI_WORK_THRESHOLD: u64 = 0xffffffc000000000;
     pub fn verify(&self) -> bool {
+        self.verify_threshold(WorkHash::RAI_WORK_THRESHOLD)
+    }
+    pub fn verify_threshold(&self, threshold: u64) -> bool {
         let w: u64 = (*self).into();
-        (w > WorkHash::RAI_WORK_THRESHOLD)
+        (w >= threshold)
     }
 }
 impl Into<u64> for WorkHash {
@@ -114,6 +303,23 @@ impl Into<u64> for WorkHash {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Balance(pub u128);
 
+impl fmt::Display for Balance {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{:032X}", self.0)
+    }
+}
+
+impl FromStr for Balance {
+    type Err = HexError;
+    fn from_str(s: &str) -> Result<Balance, HexError> {
+        let mut bytes = [0; 16];
+        read_hex(s, &mut bytes)?;
+        let high = BE::read_u64(&bytes[..8]) as u128;
+        let low = BE::read_u64(&bytes[8..]) as u128;
+        Ok(Balance(high << 64 | low))
+    }
+}
+
 impl AsRef<[u8; 16]> for Balance {
     fn as_ref(&self) -> &[u8; 16] {
         unsafe { std::mem::transmute(&self.0) }
@@ -133,3 +339,41 @@ impl std::ops::Sub for Balance {
         Balance(self.0 - rhs.0)
     }
 }
+
+#[test]
+fn test_address() {
+    use genesis::LIVE_BLOCK;
+    let address = "xrb_3t6k35gi95xu6tergt6p69ck76ogmitsa8mnijtpxm9fkcm736xtoncuohr3";
+    assert_eq!(LIVE_BLOCK.account.to_address(), address);
+    assert_eq!(PubKey::from_address(address), Ok(LIVE_BLOCK.account));
+    let nano = LIVE_BLOCK.account.to_address_with_prefix("nano_");
+    assert_eq!(PubKey::from_address(&nano), Ok(LIVE_BLOCK.account));
+
+    let bad_checksum = "xrb_3t6k35gi95xu6tergt6p69ck76ogmitsa8mnijtpxm9fkcm736xtoncuohr4";
+    assert_eq!(PubKey::from_address(bad_checksum), Err(AddressError::Checksum));
+    assert_eq!(PubKey::from_address(&address[1..]), Err(AddressError::Prefix));
+    assert_eq!(PubKey::from_address(&address[..63]), Err(AddressError::Length));
+    let bad_char = "xrb_3t6k35gi95xu6tergt6p69ck76ogmitsa8mnijtpxm9fkcm736xtoncuohr2";
+    assert_eq!(PubKey::from_address(bad_char), Err(AddressError::Character));
+}
+
+#[test]
+fn test_hex() {
+    use genesis::LIVE_BLOCK;
+    let hex = "E89208DD038FBB269987689621D52292AE9C35941A7484756ECCED92A65093BA";
+    assert_eq!(LIVE_BLOCK.account.to_string(), hex);
+    assert_eq!(hex.parse(), Ok(LIVE_BLOCK.account));
+    assert_eq!(hex.to_lowercase().parse(), Ok(LIVE_BLOCK.source));
+    assert_eq!(format!("{:?}", LIVE_BLOCK.source), hex);
+    let sig: Signature = LIVE_BLOCK.signature.to_string().parse().unwrap();
+    assert_eq!(&sig.0[..], &LIVE_BLOCK.signature.0[..]);
+    assert_eq!(LIVE_BLOCK.work.to_string(), "62F05417DD3FB691");
+    assert_eq!("62f05417dd3fb691".parse(), Ok(LIVE_BLOCK.work));
+    let balance = Balance(0x1234);
+    assert_eq!(balance.to_string(), "00000000000000000000000000001234");
+    assert_eq!(balance.to_string().parse(), Ok(balance));
+    assert_eq!("12".parse::<Hash>(), Err(HexError::Length));
+    assert_eq!("0x".parse::<Balance>(), Err(HexError::Length));
+    assert_eq!("0X05417DD3FB691".parse::<Work>(), Err(HexError::Length));
+    assert_eq!("0X05417DD3FB6912".parse::<Work>(), Err(HexError::Character));
+}
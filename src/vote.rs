@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use ed25519_dalek as ed25519;
+use blake2::Blake2b;
+use blake2::digest::{Input, VariableOutput};
+use byteorder::{ByteOrder, LE};
+
+use blockstorage::BlockStorage;
+use transaction::{verify_signature, RaiHash};
+use types::{Balance, Hash, PubKey, Signature};
+use errors::Failure;
+
+/// Hashed in front of the block hashes, so a vote signature can never be mistaken for a block's
+const VOTE_PREFIX: &[u8] = b"vote ";
+
+/// A representative's signed approval of one or more blocks
+///
+/// Votes are ordered by `sequence`, a later vote from the same representative replaces the
+/// earlier one.
+#[derive(Debug, Clone)]
+pub struct Vote {
+    pub representative: PubKey,
+    pub sequence: u64,
+    pub hashes: Vec<Hash>,
+    pub signature: Signature,
+}
+
+impl Vote {
+    /// Sign a vote for `hashes`, at most `protocol::MAX_HASHES` of them fit in one message
+    pub fn new(key: &ed25519::Keypair, sequence: u64, hashes: Vec<Hash>) -> Self {
+        let mut v = Self {
+            representative: key.public.into(),
+            sequence,
+            hashes,
+            signature: Signature::default(),
+        };
+        v.signature = key.sign::<Blake2b>(v.hash().as_ref()).into();
+        v
+    }
+    pub fn verify(&self) -> Result<(), Failure> {
+        verify_signature(self.representative, self.hash().as_ref(), self.signature)
+    }
+}
+
+impl RaiHash for Vote {
+    /// The Blake2b hash of the prefix, each block hash and the little-endian sequence number
+    fn hash(&self) -> Hash {
+        let mut sequence = [0; 8];
+        LE::write_u64(&mut sequence, self.sequence);
+        let mut hash = Blake2b::new(32).expect("Unreachable");
+        hash.process(VOTE_PREFIX);
+        for h in &self.hashes {
+            hash.process(h.as_ref())
+        }
+        hash.process(&sequence);
+        let mut bytes = Hash::default();
+        hash.variable_result(&mut bytes.0).expect("Unreachable");
+        bytes
+    }
+}
+
+/// Counts the votes in the election between the transactions competing for one root, weighted by
+/// how much balance each representative holds
+///
+/// Only the latest vote from each representative that concerns this election counts, so voting in
+/// other elections doesn't take weight away from this one. Weights are looked up when the tally is
+/// read, so they follow any changes to the ledger.
+#[derive(Debug)]
+pub struct Tally {
+    /// See `Transaction::root`
+    root: Hash,
+    votes: HashMap<PubKey, Vote>,
+}
+
+impl Tally {
+    pub fn new(root: Hash) -> Self {
+        Self {
+            root,
+            votes: HashMap::new(),
+        }
+    }
+    pub fn root(&self) -> Hash {
+        self.root
+    }
+    /// Record a vote, replacing any earlier vote from the same representative
+    ///
+    /// Fails with `Duplicate` if a vote with the same or a later sequence has already been seen,
+    /// and with `Invalid` if none of its hashes are known to compete for this root.
+    pub fn add<S: BlockStorage>(&mut self, storage: &mut S, vote: Vote) -> Result<(), Failure> {
+        if let Some(existing) = self.votes.get(&vote.representative) {
+            if existing.sequence >= vote.sequence {
+                return Err(Failure::Duplicate);
+            }
+        }
+        let candidates = storage.find_conflicts(self.root);
+        if !candidates.iter().any(|tx| vote.hashes.contains(&tx.hash())) {
+            return Err(Failure::Invalid);
+        }
+        vote.verify()?;
+        self.votes.insert(vote.representative, vote);
+        Ok(())
+    }
+    /// The latest vote seen from a representative
+    pub fn find_vote(&self, representative: PubKey) -> Option<&Vote> {
+        self.votes.get(&representative)
+    }
+    /// The total weight of the representatives whose latest vote approves `hash`
+    pub fn weight<S: BlockStorage>(&self, storage: &mut S, hash: Hash) -> Balance {
+        self.votes
+            .values()
+            .filter(|v| v.hashes.contains(&hash))
+            .fold(Balance(0), |total, v| {
+                total + storage.find_weight(v.representative)
+            })
+    }
+    /// The transaction competing for this root with the most weight behind it, along with that
+    /// weight
+    ///
+    /// Ties, including a tally without any weight, go to the transaction already in the ledger.
+    pub fn winner<S: BlockStorage>(&self, storage: &mut S) -> Option<(Hash, Balance)> {
+        let candidates: Vec<Hash> = storage
+            .find_conflicts(self.root)
+            .iter()
+            .map(|tx| tx.hash())
+            .collect();
+        // The incumbent comes first, and `max_by_key` picks the last of several maximums
+        candidates
+            .into_iter()
+            .map(|h| (h, self.weight(storage, h)))
+            .rev()
+            .max_by_key(|&(_, weight)| weight)
+    }
+}
@@ -0,0 +1,77 @@
+use ed25519_dalek as ed25519;
+use blake2::Blake2b;
+use blake2::digest::{Input, VariableOutput};
+use byteorder::{ByteOrder, BE};
+use rand::Rng;
+
+use transaction::{OpenTransaction, SendTransaction};
+use types::{Balance, Hash, PubKey};
+
+/// Derives any number of accounts from a single 32 byte seed
+///
+/// The private key of the account at `index` is the 32 byte Blake2b hash of the seed followed by
+/// the big-endian index, so a wallet can be restored from its seed alone.
+pub struct Wallet {
+    seed: [u8; 32],
+}
+
+impl Wallet {
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self { seed }
+    }
+    /// Create a wallet with a random seed
+    pub fn generate<R: Rng>(rng: &mut R) -> Self {
+        let mut seed = [0; 32];
+        rng.fill_bytes(&mut seed);
+        Self::new(seed)
+    }
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+    pub fn secret(&self, index: u32) -> ed25519::SecretKey {
+        let mut index_bytes = [0; 4];
+        BE::write_u32(&mut index_bytes, index);
+        let mut hash = Blake2b::new(32).expect("Unreachable");
+        hash.process(&self.seed);
+        hash.process(&index_bytes);
+        let mut bytes = [0; 32];
+        hash.variable_result(&mut bytes).expect("Unreachable");
+        ed25519::SecretKey::from_bytes(&bytes).expect("Unreachable")
+    }
+    pub fn keypair(&self, index: u32) -> ed25519::Keypair {
+        let secret = self.secret(index);
+        let public = ed25519::PublicKey::from_secret::<Blake2b>(&secret);
+        ed25519::Keypair { secret, public }
+    }
+    pub fn account(&self, index: u32) -> PubKey {
+        self.keypair(index).public.into()
+    }
+    /// Sign an open block for the account at `index`, the work must be filled in before inserting
+    pub fn open(&self, index: u32, source: Hash, rep: Option<PubKey>) -> OpenTransaction {
+        OpenTransaction::new_without_work(&self.keypair(index), source, rep)
+    }
+    /// Sign a send block for the account at `index`, the work must be filled in before inserting
+    pub fn send(
+        &self,
+        index: u32,
+        previous: Hash,
+        balance: Balance,
+        destination: PubKey,
+    ) -> SendTransaction {
+        SendTransaction::new_without_work(&self.keypair(index), previous, balance, destination)
+    }
+}
+
+#[test]
+fn test_derive() {
+    let wallet = Wallet::new([0; 32]);
+    assert_eq!(
+        Hash(wallet.secret(0).to_bytes()).to_string(),
+        "9F0E444C69F77A49BD0BE89DB92C38FE713E0963165CCA12FAF5712D7657120F"
+    );
+    assert_eq!(
+        wallet.account(0).to_address(),
+        "xrb_3i1aq1cchnmbn9x5rsbap8b15akfh7wj7pwskuzi7ahz8oq6cobd99d4r3b7"
+    );
+    assert!(wallet.account(0) != wallet.account(1));
+}
@@ -1,13 +1,230 @@
-use types::Work;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+use blake2::Blake2b;
+use blake2::digest::{Input, VariableOutput};
+
+use types::{Hash, Work, WorkHash};
 use transaction::RaiWork;
 use rand::{random, Rng, XorShiftRng};
 
-pub fn compute_work<T: RaiWork>(tx: &T) -> Work {
+/// How many attempts each thread makes between checking for cancellation
+const WORK_BATCH: usize = 256;
+
+/// Hash a work value together with the root it was computed over
+pub fn work_hash(root: &[u8], work: Work) -> WorkHash {
+    let mut hash = Blake2b::new(8).expect("Unreachable");
+    hash.process(work.as_ref());
+    hash.process(root);
+    let mut bytes = <[u8; 8]>::default();
+    hash.variable_result(&mut bytes).expect("Unreachable");
+    WorkHash(bytes)
+}
+
+pub fn compute_work<T: RaiWork>(tx: &T, threshold: u64) -> Work {
     let mut rng = random::<XorShiftRng>();
     loop {
         let work = rng.gen();
-        if tx.work_calculate(work).verify() {
+        if tx.work_calculate(work).verify_threshold(threshold) {
             return work;
         }
     }
 }
+
+/// The minimum work values a network accepts for each block type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Difficulty {
+    /// The threshold for send and change blocks, also the base for multipliers
+    pub send: u64,
+    /// The threshold for open and receive blocks
+    pub receive: u64,
+}
+
+impl Difficulty {
+    /// The live network uses one threshold for every block type
+    pub const LIVE: Difficulty = Difficulty {
+        send: WorkHash::RAI_WORK_THRESHOLD,
+        receive: WorkHash::RAI_WORK_THRESHOLD,
+    };
+    /// A low difficulty for test networks, quick enough to compute in tests
+    pub const TEST: Difficulty = Difficulty {
+        send: 0xff00000000000000,
+        receive: 0xf000000000000000,
+    };
+    /// The threshold that satisfies every block type
+    pub fn highest(&self) -> u64 {
+        self.send.max(self.receive)
+    }
+    /// The threshold that at least one block type accepts
+    pub fn lowest(&self) -> u64 {
+        self.send.min(self.receive)
+    }
+    /// How many times more work was needed to reach `threshold` than the base threshold
+    pub fn threshold_multiplier(&self, threshold: u64) -> f64 {
+        let base = (1u128 << 64) - self.send as u128;
+        base as f64 / ((1u128 << 64) - threshold as u128) as f64
+    }
+    /// How many times more work went into `hash` than the base threshold requires
+    pub fn multiplier(&self, hash: WorkHash) -> f64 {
+        self.threshold_multiplier(hash.into())
+    }
+    /// The threshold reached by `multiplier` times the work of the base threshold, or `None` if
+    /// the multiplier isn't a positive number
+    ///
+    /// Thresholds outside of the range of a `u64` are clamped to it.
+    pub fn from_multiplier(&self, multiplier: f64) -> Option<u64> {
+        if !multiplier.is_finite() || multiplier <= 0.0 {
+            return None;
+        }
+        let base = (1u128 << 64) - self.send as u128;
+        let threshold = (1u128 << 64).saturating_sub((base as f64 / multiplier) as u128);
+        Some(threshold.min(u64::max_value() as u128) as u64)
+    }
+}
+
+impl Default for Difficulty {
+    fn default() -> Difficulty {
+        Difficulty::LIVE
+    }
+}
+
+/// A handle that can stop a `WorkGenerator` from another thread
+#[derive(Debug, Clone)]
+pub struct WorkCanceller(Arc<AtomicBool>);
+
+impl WorkCanceller {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+}
+
+/// Searches for proof of work across several threads
+///
+/// The search is stopped when the generator is dropped.
+#[derive(Debug)]
+pub struct WorkGenerator {
+    stop: Arc<AtomicBool>,
+    attempts: Arc<AtomicUsize>,
+    started: Instant,
+    result: Receiver<Work>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl WorkGenerator {
+    /// Start searching for work over `root` whose hash exceeds `threshold`
+    pub fn start(root: Hash, threshold: u64, threads: usize) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let (sender, result) = channel();
+        let threads = (0..threads.max(1))
+            .map(|_| {
+                let stop = stop.clone();
+                let attempts = attempts.clone();
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    let mut rng = random::<XorShiftRng>();
+                    while !stop.load(Ordering::Relaxed) {
+                        for _ in 0..WORK_BATCH {
+                            let work = rng.gen();
+                            if work_hash(&root.0, work).verify_threshold(threshold) {
+                                // Only the first result is ever received
+                                let _ = sender.send(work);
+                                stop.store(true, Ordering::Relaxed);
+                                break;
+                            }
+                        }
+                        attempts.fetch_add(WORK_BATCH, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        Self {
+            stop,
+            attempts,
+            started: Instant::now(),
+            result,
+            threads,
+        }
+    }
+    /// Start searching for work for the given transaction
+    pub fn for_transaction<T: RaiWork>(tx: &T, threshold: u64, threads: usize) -> Self {
+        Self::start(tx.work_root(), threshold, threads)
+    }
+    /// Stop searching, unless work has already been found `wait` will return `None`
+    pub fn cancel(&self) {
+        self.stop.store(true, Ordering::Relaxed)
+    }
+    /// Get a handle that can cancel this search from another thread
+    pub fn canceller(&self) -> WorkCanceller {
+        WorkCanceller(self.stop.clone())
+    }
+    /// The number of work values tried so far, counted in batches of `WORK_BATCH`
+    pub fn attempts(&self) -> usize {
+        self.attempts.load(Ordering::Relaxed)
+    }
+    /// The average number of attempts per second since the search started
+    pub fn hashrate(&self) -> f64 {
+        let elapsed = self.started.elapsed();
+        let secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+        self.attempts() as f64 / secs
+    }
+    /// Get the result if it has been found, without blocking
+    pub fn try_result(&self) -> Option<Work> {
+        self.result.try_recv().ok()
+    }
+    /// Block until work is found, or `None` if the search was cancelled
+    pub fn wait(mut self) -> Option<Work> {
+        let work = self.result.recv().ok();
+        self.cancel();
+        for t in self.threads.drain(..) {
+            let _ = t.join();
+        }
+        work
+    }
+}
+
+impl Drop for WorkGenerator {
+    fn drop(&mut self) {
+        self.cancel()
+    }
+}
+
+#[test]
+fn test_work_generator() {
+    let root = Hash([7; 32]);
+    let threshold = 0xff00000000000000;
+    let work = WorkGenerator::start(root, threshold, 4).wait().unwrap();
+    assert!(work_hash(&root.0, work).verify_threshold(threshold));
+}
+
+#[test]
+fn test_work_cancel() {
+    use std::time::Duration;
+    // Reaching the maximum threshold is practically impossible, so this won't finish on its own
+    let generator = WorkGenerator::start(Hash([7; 32]), u64::max_value(), 2);
+    let canceller = generator.canceller();
+    thread::sleep(Duration::from_millis(10));
+    assert!(generator.attempts() > 0);
+    assert!(generator.hashrate() > 0.0);
+    thread::spawn(move || canceller.cancel());
+    assert!(generator.wait().is_none());
+}
+
+#[test]
+fn test_difficulty_multiplier() {
+    let d = Difficulty::TEST;
+    assert_eq!(d.threshold_multiplier(d.send), 1.0);
+    assert_eq!(d.threshold_multiplier(d.receive), 1.0 / 16.0);
+    assert_eq!(d.from_multiplier(16.0), Some(0xfff0000000000000));
+    assert_eq!(d.from_multiplier(0.5), Some(0xfe00000000000000));
+    // Easier than no work at all, or harder than the maximum threshold
+    assert_eq!(d.from_multiplier(1e-9), Some(0));
+    assert_eq!(d.from_multiplier(1e30), Some(u64::max_value()));
+    assert_eq!(d.from_multiplier(0.0), None);
+    assert_eq!(d.from_multiplier(-1.0), None);
+    assert_eq!(d.from_multiplier(::std::f64::NAN), None);
+    assert_eq!(d.from_multiplier(::std::f64::INFINITY), None);
+    assert_eq!(d.multiplier(WorkHash([0, 0, 0, 0, 0, 0, 0xf0, 0xff])), 16.0);
+}